@@ -0,0 +1,283 @@
+use core::convert::TryInto;
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::{
+	id3::{
+		ID3CommentFrame, ID3Frame, ID3FrameFlags, ID3FrameType, ID3PictureFrame, ID3TextFrame, ID3UserTextFrame,
+		ID3UserUrlFrame,
+	},
+	TagError,
+};
+
+/// An APEv1 or APEv2 tag found at the end of a file, before any ID3v1 tag.
+///
+/// The tag is made of a 32 byte footer, optionally preceded by a header of the same layout (APEv2 only), and a list
+/// of items between them. All numbers are little-endian.
+pub struct APETag {
+	/// 1000 for APEv1, 2000 for APEv2
+	pub version: u32,
+	pub has_header: bool,
+	pub read_only: bool,
+	pub items: Vec<APEItem>,
+}
+
+pub struct APEItem {
+	/// ASCII, compared without case
+	pub key: String,
+	pub value: APEValue,
+	pub read_only: bool,
+}
+
+pub enum APEValue {
+	/// UTF-8, with multiple values separated by NUL
+	Text(Vec<String>),
+	Binary(Vec<u8>),
+	/// A URL or file path, UTF-8
+	Locator(String),
+}
+
+const PREAMBLE: &[u8; 8] = b"APETAGEX";
+const FOOTER_SIZE: usize = 32;
+
+const HAS_HEADER: u32 = 1 << 31;
+const READ_ONLY: u32 = 1;
+
+impl APETag {
+	/// Read the APE tag which ends at `end`, which is the end of the file or the start of its ID3v1 tag.
+	///
+	/// Returns the tag along with the offset at which it starts, including the header if there is one.
+	pub fn read(content: &[u8], end: usize) -> Result<Option<(APETag, usize)>, TagError> {
+		let footer_start = match end.checked_sub(FOOTER_SIZE) {
+			Some(x) if end <= content.len() => x,
+			_ => return Ok(None),
+		};
+		let footer = &content[footer_start..end];
+		if &footer[0..8] != PREAMBLE {
+			return Ok(None);
+		}
+		let version = u32::from_le_bytes(footer[8..12].try_into().unwrap());
+		// The size covers the items and the footer, but not the header
+		let size = u32::from_le_bytes(footer[12..16].try_into().unwrap()) as usize;
+		let count = u32::from_le_bytes(footer[16..20].try_into().unwrap());
+		let flags = u32::from_le_bytes(footer[20..24].try_into().unwrap());
+		let truncated = TagError::UnexpectedEnd {
+			frame: None,
+			offset: footer_start,
+		};
+
+		let items_start = match end.checked_sub(size) {
+			Some(x) if size >= FOOTER_SIZE => x,
+			_ => return Err(truncated),
+		};
+		// APEv1 has neither a header nor flags
+		let has_header = version >= 2000 && flags & HAS_HEADER != 0;
+		let start = if has_header {
+			items_start.checked_sub(FOOTER_SIZE).ok_or(truncated)?
+		} else {
+			items_start
+		};
+
+		let mut items = Vec::new();
+		let mut ix = items_start;
+		for _ in 0..count {
+			let (item, item_size) = read_item(&content[ix..footer_start]).map_err(|e| e.shifted(ix))?;
+			items.push(item);
+			ix += item_size;
+		}
+
+		Ok(Some((
+			APETag {
+				version,
+				has_header,
+				read_only: version >= 2000 && flags & READ_ONLY != 0,
+				items,
+			},
+			start,
+		)))
+	}
+
+	/// Read the APE tag which ends at `end` of `reader`, as `read`, reading only the tag.
+	pub fn read_from<R: Read + Seek>(reader: &mut R, end: u64) -> Result<Option<(APETag, u64)>, TagError> {
+		if end < FOOTER_SIZE as u64 {
+			return Ok(None);
+		}
+		let mut footer = [0u8; FOOTER_SIZE];
+		reader.seek(SeekFrom::Start(end - FOOTER_SIZE as u64))?;
+		reader.read_exact(&mut footer)?;
+		if &footer[0..8] != PREAMBLE {
+			return Ok(None);
+		}
+		// Read enough for the items and a header, leaving `read` to check the sizes
+		let size = u32::from_le_bytes(footer[12..16].try_into().unwrap()) as u64;
+		let start = end.saturating_sub(size + FOOTER_SIZE as u64);
+		let mut content = vec![0u8; (end - start) as usize];
+		reader.seek(SeekFrom::Start(start))?;
+		reader.read_exact(&mut content)?;
+		let tag = APETag::read(&content, content.len()).map_err(|e| e.shifted(start as usize))?;
+		Ok(tag.map(|(tag, tag_start)| (tag, start + tag_start as u64)))
+	}
+}
+
+/// Read one item, returning it with its size in bytes.
+fn read_item(content: &[u8]) -> Result<(APEItem, usize), TagError> {
+	if content.len() < 8 {
+		return Err(TagError::UnexpectedEnd { frame: None, offset: 0 });
+	}
+	let value_len = u32::from_le_bytes(content[0..4].try_into().unwrap()) as usize;
+	let flags = u32::from_le_bytes(content[4..8].try_into().unwrap());
+	let key_len = match content[8..].iter().position(|b| *b == 0) {
+		Some(x) => x,
+		None => {
+			return Err(TagError::UnexpectedEnd {
+				frame: None,
+				offset: content.len(),
+			});
+		}
+	};
+	let key = &content[8..8 + key_len];
+	if key.len() < 2 || !key.iter().all(|b| (0x20..0x7F).contains(b)) {
+		return Err(TagError::InvalidText { frame: None, offset: 8 });
+	}
+	let value_start = 8 + key_len + 1;
+	let value = match content.get(value_start..value_start.saturating_add(value_len)) {
+		Some(x) => x,
+		None => {
+			return Err(TagError::UnexpectedEnd {
+				frame: None,
+				offset: content.len(),
+			});
+		}
+	};
+	let text = || match std::str::from_utf8(value) {
+		Ok(s) => Ok(s.to_string()),
+		Err(e) => Err(TagError::InvalidText {
+			frame: None,
+			offset: value_start + e.valid_up_to(),
+		}),
+	};
+	let value = match (flags >> 1) & 0b11 {
+		0 => APEValue::Text(text()?.split('\0').map(String::from).collect()),
+		2 => APEValue::Locator(text()?),
+		// 3 is reserved
+		_ => APEValue::Binary(value.to_vec()),
+	};
+
+	Ok((
+		APEItem {
+			key: String::from_utf8_lossy(key).into_owned(),
+			value,
+			read_only: flags & READ_ONLY != 0,
+		},
+		value_start + value_len,
+	))
+}
+
+/// APE keys with an equivalent ID3v2 text frame.
+const TEXT_KEYS: [(&str, &[u8; 4]); 16] = [
+	("Title", b"TIT2"),
+	("Subtitle", b"TIT3"),
+	("Artist", b"TPE1"),
+	("Album Artist", b"TPE2"),
+	("AlbumArtist", b"TPE2"),
+	("Conductor", b"TPE3"),
+	("Album", b"TALB"),
+	("Year", b"TDRC"),
+	("Track", b"TRCK"),
+	("Disc", b"TPOS"),
+	("Genre", b"TCON"),
+	("Composer", b"TCOM"),
+	("Publisher", b"TPUB"),
+	("Copyright", b"TCOP"),
+	("ISRC", b"TSRC"),
+	("Media", b"TMED"),
+];
+
+impl APEItem {
+	/// The equivalent ID3v2.4 frame. Text items without a matching frame become TXXX frames and locators become
+	/// WXXX frames. Of the binary items only cover art can be converted.
+	pub fn frame(&self) -> Option<ID3Frame> {
+		let (id, data) = match &self.value {
+			APEValue::Text(values) => {
+				let known = TEXT_KEYS.iter().find(|(key, _)| key.eq_ignore_ascii_case(&self.key));
+				if let Some((_, id)) = known {
+					(
+						**id,
+						ID3FrameType::Text(ID3TextFrame {
+							values: values.clone(),
+							encoding: 0,
+						}),
+					)
+				} else if self.key.eq_ignore_ascii_case("Comment") || self.key.eq_ignore_ascii_case("Lyrics") {
+					let id = if self.key.eq_ignore_ascii_case("Comment") {
+						*b"COMM"
+					} else {
+						*b"USLT"
+					};
+					(
+						id,
+						ID3FrameType::Comment(ID3CommentFrame {
+							language: *b"eng",
+							content_desc: String::new(),
+							text: values.join("\n"),
+							encoding: 0,
+						}),
+					)
+				} else {
+					(
+						*b"TXXX",
+						ID3FrameType::UserText(ID3UserTextFrame {
+							description: self.key.clone(),
+							values: values.clone(),
+							encoding: 0,
+						}),
+					)
+				}
+			}
+			APEValue::Locator(url) => (
+				*b"WXXX",
+				ID3FrameType::UserUrl(ID3UserUrlFrame {
+					description: self.key.clone(),
+					url: url.clone(),
+					encoding: 0,
+				}),
+			),
+			APEValue::Binary(data) => {
+				let pic_type = if self.key.eq_ignore_ascii_case("Cover Art (Front)") {
+					3
+				} else if self.key.eq_ignore_ascii_case("Cover Art (Back)") {
+					4
+				} else {
+					return None;
+				};
+				// Cover art is a file name followed by the image
+				let name_len = data.iter().position(|b| *b == 0)?;
+				let image = &data[name_len + 1..];
+				(
+					*b"APIC",
+					ID3FrameType::Picture(ID3PictureFrame {
+						mime: image_mime(image, &String::from_utf8_lossy(&data[..name_len])),
+						pic_type,
+						description: String::new(),
+						data: image.to_vec(),
+					}),
+				)
+			}
+		};
+		Some(ID3Frame {
+			id,
+			flags: ID3FrameFlags::default(),
+			data,
+		})
+	}
+}
+
+fn image_mime(data: &[u8], file_name: &str) -> String {
+	let lower = file_name.to_ascii_lowercase();
+	if data.starts_with(b"\x89PNG") || lower.ends_with(".png") {
+		String::from("image/png")
+	} else if data.starts_with(b"GIF8") || lower.ends_with(".gif") {
+		String::from("image/gif")
+	} else {
+		String::from("image/jpeg")
+	}
+}
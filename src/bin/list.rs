@@ -1,10 +1,22 @@
 use std::{fs::DirEntry, path::Path};
-use tag::{id3, id3::ID3FrameType, parse_mp4_frames};
+use tag::{
+	ape::{APETag, APEValue},
+	codepage, id3,
+	id3::ID3FrameType,
+	id3v1::{self, ID3v1Tag},
+	mpeg::{AudioProperties, ChannelMode, MpegVersion},
+	parse_mp4_frames,
+};
 
 fn main() -> Result<(), i32> {
 	let args: Vec<String> = std::env::args().collect();
 
-	let opts = getopts::Options::new();
+	let mut opts = getopts::Options::new();
+	opts.optflag(
+		"",
+		"chapters",
+		"List only the chapters, one \"HH:MM:SS title\" per line",
+	);
 	let matches = match opts.parse(&args[1..]) {
 		Ok(x) => x,
 		Err(e) => {
@@ -19,6 +31,7 @@ fn main() -> Result<(), i32> {
 	}
 
 	let path = &matches.free[0];
+	let chapters = matches.opt_present("chapters");
 
 	let metadata = match std::fs::metadata(path) {
 		Ok(x) => x,
@@ -29,7 +42,7 @@ fn main() -> Result<(), i32> {
 	};
 
 	if metadata.is_file() {
-		return match list_frames(Path::new(path)) {
+		return match list_frames(Path::new(path), chapters) {
 			Ok(_) => Ok(()),
 			Err(e) => {
 				println!("{}", e);
@@ -49,20 +62,28 @@ fn main() -> Result<(), i32> {
 	let mut paths: Vec<DirEntry> = files.map(|r| r.unwrap()).collect();
 
 	paths.sort_by_key(|dir| dir.path());
+	let mut failed = false;
 	for path in paths {
-		if let Err(e) = list_frames(&path.path()) {
-			println!("Error on {}: {}", path.file_name().to_str().unwrap(), e);
-			return Err(1);
+		if let Err(e) = list_frames(&path.path(), chapters) {
+			println!("Error on {}: {}", path.file_name().to_string_lossy(), e);
+			failed = true;
 		}
 	}
 
+	if failed {
+		return Err(1);
+	}
 	Ok(())
 }
 
-fn list_frames(path: &Path) -> Result<(), String> {
+fn list_frames(path: &Path, chapters: bool) -> Result<(), String> {
 	println!("Name: {}", path.display());
 	if path.file_name().unwrap_or_default().to_string_lossy().ends_with(".mp3") {
-		list_mp3_frames(path)?;
+		if chapters {
+			list_mp3_chapters(path)?;
+		} else {
+			list_mp3_frames(path)?;
+		}
 	} else if path.file_name().unwrap_or_default().to_string_lossy().ends_with(".m4a") {
 		list_mp4_frames(path)?;
 	} else {
@@ -72,21 +93,34 @@ fn list_frames(path: &Path) -> Result<(), String> {
 }
 
 fn list_mp3_frames(path: &Path) -> Result<(), String> {
-	let content = match std::fs::read(path) {
+	let mut file = match std::fs::File::open(path) {
 		Ok(s) => s,
 		Err(e) => {
 			return Err(format!("Could not open file: {}: {}", path.display(), e));
 		}
 	};
 
-	let (frames, _) = tag::read_id3_frames(&content);
+	let (frames, unconverted, id3_size) = match tag::read_id3_tag_from(&mut file) {
+		Ok(tag) => {
+			let id3_size = tag.size();
+			(tag.frames, tag.unconverted, id3_size)
+		}
+		// A file may have only an ID3v1 tag
+		Err(tag::TagError::MissingHeader) => (Vec::new(), Vec::new(), 0),
+		Err(e) => {
+			return Err(format!("Could not read ID3 tag: {}: {}", path.display(), e));
+		}
+	};
+	let codepage = codepage::detect(&frames);
 	for frame in frames {
 		println!("---------------");
-		let flag = ((frame.flags[0] as u16) << 8) & frame.flags[1] as u16;
 		println!(
-			"{}{}{}{} {:#04X}",
-			frame.id[0] as char, frame.id[1] as char, frame.id[2] as char, frame.id[3] as char, flag
+			"{}{}{}{}",
+			frame.id[0] as char, frame.id[1] as char, frame.id[2] as char, frame.id[3] as char
 		);
+		if !frame.flags.is_empty() {
+			println!("      flags: {}", frame.flags.display());
+		}
 		match frame.data {
 			ID3FrameType::Comment(f) => {
 				println!(
@@ -108,25 +142,269 @@ fn list_mp3_frames(path: &Path) -> Result<(), String> {
 				println!("       size: {} bytes", f.data.len());
 			}
 			ID3FrameType::Text(f) => {
-				if frame.id == *b"TXXX" {
-					let mut description = String::new();
-					let mut char_count = 0;
-					for x in f.data.chars() {
-						char_count += 1;
-						if x == 0 as char {
-							break;
-						}
-						description.push(x);
+				println!("   encoding: {}", f.encoding);
+				for value in &f.values {
+					println!("       text: {}", value);
+				}
+			}
+			ID3FrameType::UserText(f) => {
+				println!("   encoding: {}", f.encoding);
+				println!("description: {}", f.description);
+				for value in &f.values {
+					println!("       text: {}", value);
+				}
+			}
+			ID3FrameType::Url(f) => {
+				println!("        url: {}", f.url);
+			}
+			ID3FrameType::UserUrl(f) => {
+				println!("   encoding: {}", f.encoding);
+				println!("description: {}", f.description);
+				println!("        url: {}", f.url);
+			}
+			ID3FrameType::UniqueFileId(f) => {
+				println!("      owner: {}", f.owner);
+				println!(" identifier: {}", String::from_utf8_lossy(&f.identifier));
+			}
+			ID3FrameType::Private(f) => {
+				println!("      owner: {}", f.owner);
+				println!("       size: {} bytes", f.data.len());
+			}
+			ID3FrameType::Popularimeter(f) => {
+				println!("      email: {}", f.email);
+				println!("     rating: {}/255", f.rating);
+				if let Some(counter) = f.counter {
+					println!("    counter: {}", counter);
+				}
+			}
+			ID3FrameType::PlayCounter(f) => {
+				println!("    counter: {}", f.counter);
+			}
+			ID3FrameType::SyncLyrics(f) => {
+				println!(
+					"   language: {}{}{}",
+					f.language[0] as char, f.language[1] as char, f.language[2] as char
+				);
+				println!("description: {}", f.description);
+				println!("   encoding: {}", f.encoding);
+				println!("       type: {}", f.content_type);
+				let unit = if f.timestamp_format == 1 { "frames" } else { "ms" };
+				for (text, timestamp) in &f.lyrics {
+					println!("{:>8} {}: {}", timestamp, unit, text.trim_start_matches('\n'));
+				}
+			}
+			ID3FrameType::Chapter(f) => {
+				println!(" element id: {}", f.element_id);
+				println!("       time: {} - {} ms", f.start_time, f.end_time);
+				if f.start_offset != u32::MAX || f.end_offset != u32::MAX {
+					println!("     offset: {} - {}", f.start_offset, f.end_offset);
+				}
+				for sub_frame in &f.frames {
+					println!("      frame: {}", sub_frame.display());
+				}
+			}
+			ID3FrameType::TableOfContents(f) => {
+				println!(" element id: {}", f.element_id);
+				println!("  top level: {}", f.top_level);
+				println!("    ordered: {}", f.ordered);
+				println!("   children: {}", f.children.join(", "));
+				for sub_frame in &f.frames {
+					println!("      frame: {}", sub_frame.display());
+				}
+			}
+			ID3FrameType::GeneralObject(f) => {
+				println!("       mime: {}", f.mime);
+				println!("   filename: {}", f.filename);
+				println!("description: {}", f.description);
+				println!("       size: {} bytes", f.data.len());
+			}
+			ID3FrameType::RelativeVolume(f) => {
+				println!("         id: {}", f.identification);
+				for channel in &f.channels {
+					match channel.peak_ratio() {
+						Some(peak) => println!(
+							"    channel: {} {:+.2} dB, peak {:.6}",
+							channel.channel_type,
+							channel.gain_db(),
+							peak
+						),
+						None => println!("    channel: {} {:+.2} dB", channel.channel_type, channel.gain_db()),
 					}
-					let value: String = f.data.chars().skip(char_count).collect();
-					println!("{}: {}", description, value);
-				} else {
-					println!("   encoding: {}", f.encoding);
-					println!("       text: {}", f.data);
 				}
 			}
+			ID3FrameType::Raw(data) => {
+				println!("       size: {} bytes", data.len());
+			}
 		};
 	}
+	for frame in unconverted {
+		println!("---------------");
+		println!("Could not be converted to ID3v2.4: {}", frame.display());
+	}
+	if let Some(codepage) = codepage {
+		println!("---------------");
+		println!("ISO-8859-1 text looks like {}", codepage.name());
+	}
+
+	let id3v1 = match ID3v1Tag::read_from(&mut file) {
+		Ok(x) => x,
+		Err(e) => {
+			return Err(format!("Could not read ID3v1 tag: {}: {}", path.display(), e));
+		}
+	};
+	let file_len = match file.metadata() {
+		Ok(x) => x.len(),
+		Err(e) => {
+			return Err(format!("Could not open file: {}: {}", path.display(), e));
+		}
+	};
+	let ape_end = file_len - id3v1.as_ref().map_or(0, |v1| v1.size() as u64);
+	let audio_end = match APETag::read_from(&mut file, ape_end) {
+		Ok(Some((ape, ape_start))) => {
+			println!("---------------");
+			println!(
+				"APEv{}{}",
+				ape.version / 1000,
+				if ape.read_only { " (read only)" } else { "" }
+			);
+			for item in &ape.items {
+				match &item.value {
+					APEValue::Text(values) => {
+						for value in values {
+							println!("{:>11}: {}", item.key, value);
+						}
+					}
+					APEValue::Binary(data) => println!("{:>11}: {} bytes", item.key, data.len()),
+					APEValue::Locator(url) => println!("{:>11}: -> {}", item.key, url),
+				}
+			}
+			ape_start
+		}
+		Ok(None) => ape_end,
+		Err(e) => {
+			return Err(format!("Could not read APE tag: {}: {}", path.display(), e));
+		}
+	};
+
+	if let Some(v1) = id3v1 {
+		println!("---------------");
+		println!("ID3v1{}", if v1.extended.is_some() { " (extended)" } else { "" });
+		let ext = v1.extended.clone().unwrap_or_default();
+		println!("      title: {}{}", v1.title, ext.title);
+		println!("     artist: {}{}", v1.artist, ext.artist);
+		println!("      album: {}{}", v1.album, ext.album);
+		println!("       year: {}", v1.year);
+		println!("    comment: {}", v1.comment);
+		if let Some(track) = v1.track {
+			println!("      track: {}", track);
+		}
+		println!(
+			"      genre: {} ({})",
+			v1.genre,
+			id3v1::genre_name(v1.genre).unwrap_or("???")
+		);
+		if !ext.genre.is_empty() {
+			println!(" free genre: {}", ext.genre);
+		}
+		if ext.speed != 0 {
+			println!("      speed: {}", ext.speed);
+		}
+		if !ext.start_time.is_empty() || !ext.end_time.is_empty() {
+			println!("       time: {} - {}", ext.start_time, ext.end_time);
+		}
+	}
+
+	let audio = match AudioProperties::read_from(&mut file, id3_size as u64, audio_end) {
+		Ok(x) => x,
+		Err(e) => {
+			return Err(format!("Could not read audio: {}: {}", path.display(), e));
+		}
+	};
+	if let Some(audio) = audio {
+		list_audio(&audio);
+	}
+
+	Ok(())
+}
+
+fn list_audio(audio: &AudioProperties) {
+	let header = &audio.header;
+	println!("---------------");
+	let version = match header.version {
+		MpegVersion::V1 => "1",
+		MpegVersion::V2 => "2",
+		MpegVersion::V25 => "2.5",
+	};
+	println!("MPEG-{} Layer {}", version, "I".repeat(header.layer as usize));
+	println!("     offset: {}", audio.offset);
+	println!("    bitrate: {} kbps", audio.bitrate());
+	println!("sample rate: {} Hz", header.sample_rate);
+	let channels = match header.channel_mode {
+		ChannelMode::Stereo => "stereo",
+		ChannelMode::JointStereo => "joint stereo",
+		ChannelMode::DualChannel => "dual channel",
+		ChannelMode::Mono => "mono",
+	};
+	println!("   channels: {}", channels);
+	let ms = audio.duration_ms();
+	println!(
+		"   duration: {}:{:02}.{:03} ({} samples)",
+		ms / 60_000,
+		ms / 1000 % 60,
+		ms % 1000,
+		audio.samples()
+	);
+	if let Some(vbr) = &audio.vbr {
+		println!("     header: {:?}", vbr.kind);
+		if let Some(frames) = vbr.frames {
+			println!("     frames: {}", frames);
+		}
+		if let Some(bytes) = vbr.bytes {
+			println!("      bytes: {}", bytes);
+		}
+		if !vbr.toc.is_empty() {
+			println!("        toc: {} entries", vbr.toc.len());
+		}
+		if let Some(encoder) = &vbr.encoder {
+			println!("    encoder: {}", encoder);
+			println!("      delay: {} samples", vbr.encoder_delay);
+			println!("    padding: {} samples", vbr.encoder_padding);
+		}
+	}
+}
+
+fn list_mp3_chapters(path: &Path) -> Result<(), String> {
+	let mut file = match std::fs::File::open(path) {
+		Ok(s) => s,
+		Err(e) => {
+			return Err(format!("Could not open file: {}: {}", path.display(), e));
+		}
+	};
+
+	let (frames, _) = match tag::read_id3_frames_from(&mut file) {
+		Ok(x) => x,
+		Err(e) => {
+			return Err(format!("Could not read ID3 tag: {}: {}", path.display(), e));
+		}
+	};
+	let mut chapters: Vec<&id3::ID3ChapterFrame> = frames
+		.iter()
+		.filter_map(|frame| match &frame.data {
+			ID3FrameType::Chapter(f) => Some(f),
+			_ => None,
+		})
+		.collect();
+	chapters.sort_by_key(|f| f.start_time);
+	for chapter in chapters {
+		let seconds = chapter.start_time / 1000;
+		println!(
+			"{:02}:{:02}:{:02} {}",
+			seconds / 3600,
+			seconds / 60 % 60,
+			seconds % 60,
+			chapter.title()
+		);
+	}
 
 	Ok(())
 }
@@ -28,7 +28,12 @@ fn read_comment(path: &Path) -> Result<String, String> {
 		}
 	};
 
-	let (frames, _) = tag::read_id3_frames(&content);
+	let (frames, _) = match tag::read_id3_frames(&content) {
+		Ok(x) => x,
+		Err(e) => {
+			return Err(format!("Could not read ID3 tag: {}: {}", path.display(), e));
+		}
+	};
 	let comments = frames.iter().try_fold(Vec::new(), |mut acc, frame| {
 		let code = std::str::from_utf8(&frame.id).unwrap();
 		if code == "COMM" {
@@ -1,14 +1,19 @@
 use std::{
 	collections::HashSet,
 	fs::DirEntry,
-	io::Write,
+	io::{Seek, SeekFrom, Write},
 	path::{Path, PathBuf},
 	vec::Vec,
 };
 
 use tag::{
+	ape::APETag,
+	codepage,
 	id3::{self, ID3CommentFrame, ID3Frame, ID3PictureFrame},
+	id3v1::ID3v1Tag,
 	mp4,
+	mpeg::AudioProperties,
+	replaygain::{self, ReplayGain},
 };
 
 #[derive(Clone)]
@@ -19,34 +24,81 @@ struct PictureArg {
 	path: String,
 }
 
+#[derive(Clone)]
+struct ObjectArg {
+	mime: String,
+	description: String,
+	path: String,
+}
+
 #[derive(Clone)]
 struct Flags {
 	title: Option<String>,
-	artist: Option<String>,
+	artist: Vec<String>,
 	track: Option<String>,
 	album: Option<String>,
 	sort_album: Option<String>,
-	genre: Option<String>, // Genre
+	genre: Vec<String>,
 	record_date: Option<String>,
 	comment: Option<String>,
 	combine_comments: bool,
 	pictures: Vec<PictureArg>,
 	remove: HashSet<String>,
+	txxx: Vec<(String, String)>, // (description, value)
+	remove_txxx: HashSet<String>,
+	urls: Vec<([u8; 4], String)>,
+	wxxx: Vec<(String, String)>, // (description, URL)
+	ufid: Vec<(String, String)>, // (owner, identifier)
+	remove_ufid: HashSet<String>,
+	rating: Option<u8>,
+	play_count: Option<u64>,
+	replay_gain: ReplayGain,
+	lrc: Option<String>,
+	extract_lrc: Option<PathBuf>,
+	chapters: Option<String>,
+	objects: Vec<ObjectArg>,
+	extract_objects: Option<PathBuf>,
+	/// Only extracting from the file, which is left as it is
+	extract_only: bool,
+	id3_version: u8,
+	unsynchronise: bool,
+	crc: bool,
+	merge_id3v1: bool,
+	write_id3v1: bool,
+	strip_ape: bool,
+	import_ape: bool,
+	tlen: bool,
+	padding: u32,
+	text_encoding: id3::TextEncoding,
+	fix_encoding: Option<Codepage>,
 	//
 	out_path: PathBuf,
 }
 
+/// The codepage to decode ISO-8859-1 text as
+#[derive(Clone, Copy)]
+enum Codepage {
+	/// Guessed for each file
+	Detect,
+	Fixed(&'static codepage::Encoding),
+}
+
 fn main() -> Result<(), i32> {
 	let args: Vec<String> = std::env::args().collect();
 
 	let mut opts = getopts::Options::new();
 	opts.optopt("", "title", "Title data to add", "TITLE");
-	opts.optopt("", "artist", "Artist / Album Artist data to add", "ARTIST");
+	opts.optmulti(
+		"",
+		"artist",
+		"Artist / Album Artist data to add. May be repeated",
+		"ARTIST",
+	);
 	opts.optopt("", "track", "Track data to add", "TRACK");
 	opts.optopt("", "album", "Album data to add", "ALBUM");
 	opts.optopt("", "sort-album", "Sort Album name", "ALBUM");
 	opts.optopt("", "record-date", "Date of recording", "YYYY-MM-DD");
-	opts.optopt("", "genre", "Genre", "GENRE");
+	opts.optmulti("", "genre", "Genre. May be repeated", "GENRE");
 	opts.optopt("", "comment", "Comment data to add", "TEXT");
 	opts.optflag("", "combine_comments", "Combine comment frames");
 	opts.optmulti(
@@ -61,7 +113,120 @@ fn main() -> Result<(), i32> {
 		"Semicolon-separated list of frame types to remove",
 		"TXXX;COMM",
 	);
-	opts.optopt("", "output", "Path to output file", "FILE");
+	opts.optmulti("", "txxx", "User defined text to set. May be repeated", "DESC=VALUE");
+	opts.optmulti(
+		"",
+		"remove-txxx",
+		"User defined text to remove. May be repeated",
+		"DESC",
+	);
+	opts.optmulti(
+		"",
+		"url",
+		"URL link to set, such as WOAF=https://example.com/episode. May be repeated",
+		"FRAME=URL",
+	);
+	opts.optmulti("", "wxxx", "User defined URL link to set. May be repeated", "DESC=URL");
+	opts.optmulti(
+		"",
+		"ufid",
+		"Unique file identifier to set for an owner. May be repeated",
+		"OWNER=ID",
+	);
+	opts.optmulti(
+		"",
+		"remove-ufid",
+		"Owner of a unique file identifier to remove. May be repeated",
+		"OWNER",
+	);
+	opts.optopt(
+		"",
+		"rating",
+		"Rating from 1 (worst) to 255 (best), or 0 for unknown",
+		"0-255",
+	);
+	opts.optopt("", "play-count", "Number of times the file has been played", "COUNT");
+	opts.optopt("", "track-gain", "ReplayGain track gain", "DB");
+	opts.optopt(
+		"",
+		"track-peak",
+		"ReplayGain track peak, where 1.0 is full scale",
+		"PEAK",
+	);
+	opts.optopt("", "album-gain", "ReplayGain album gain", "DB");
+	opts.optopt(
+		"",
+		"album-peak",
+		"ReplayGain album peak, where 1.0 is full scale",
+		"PEAK",
+	);
+	opts.optopt("", "lrc", "LRC file of timed lyrics to embed", "FILE");
+	opts.optopt(
+		"",
+		"extract-lrc",
+		"Path to write the embedded timed lyrics to as LRC",
+		"FILE",
+	);
+	opts.optopt(
+		"",
+		"chapters",
+		"Text file of chapters to import, one \"HH:MM:SS title\" per line",
+		"FILE",
+	);
+	opts.optmulti(
+		"",
+		"object",
+		"File to attach as a general encapsulated object in format {mime type}:{description}:{path}",
+		"OBJECT",
+	);
+	opts.optopt(
+		"",
+		"extract-objects",
+		"Directory to write the general encapsulated objects to",
+		"DIR",
+	);
+	opts.optopt("", "id3-version", "ID3v2 version to write (default 4)", "3|4");
+	opts.optflag("", "unsynchronise", "Apply unsynchronisation to the ID3 tag");
+	opts.optflag("", "crc", "Write an extended header with a CRC-32 of the ID3 tag");
+	opts.optflag("", "merge-id3v1", "Use the ID3v1 tag when there is no ID3v2 tag");
+	opts.optflag(
+		"",
+		"write-id3v1",
+		"Write an ID3v1 tag from the new ID3v2 tag instead of removing it",
+	);
+	opts.optflag("", "strip-ape", "Remove the APE tag");
+	opts.optflag(
+		"",
+		"import-ape",
+		"Add the fields of the APE tag which are missing from the ID3v2 tag, then remove the APE tag",
+	);
+	opts.optflag("", "tlen", "Write a TLEN frame with the duration of the audio");
+	opts.optopt(
+		"",
+		"padding",
+		"Padding to reserve when the whole file is rewritten, so later edits can be made in place (default 0)",
+		"BYTES",
+	);
+	opts.optopt(
+		"",
+		"text-encoding",
+		"Encoding to write text in: latin1 (default), utf16, utf16be or utf8. Text which ISO-8859-1 can't represent is \
+		 written as UTF-16 for ID3v2.3 and UTF-8 for ID3v2.4, and ID3v2.3 writes utf16be and utf8 as utf16",
+		"ENCODING",
+	);
+	opts.optopt(
+		"",
+		"fix-encoding",
+		"Decode text marked as ISO-8859-1 as a legacy codepage such as CP1251, Shift_JIS, GBK or Big5, or guess it for \
+		 each file with \"auto\". The text is then written in an encoding which can represent it",
+		"CODEPAGE",
+	);
+	opts.optopt(
+		"",
+		"output",
+		"Path to output file. When it is the input file and the new tag fits in the old one, only the tag is rewritten",
+		"FILE",
+	);
 	let matches = match opts.parse(&args[1..]) {
 		Ok(x) => x,
 		Err(e) => {
@@ -78,13 +243,105 @@ fn main() -> Result<(), i32> {
 		}
 	};
 
+	let id3_version = match matches.opt_str("id3-version").as_deref() {
+		None | Some("4") => 4,
+		Some("3") => 3,
+		Some(x) => {
+			println!("id3-version must be 3 or 4. Found \"{}\"", x);
+			return Err(1);
+		}
+	};
+
+	let padding = match matches.opt_str("padding").map(|x| x.parse::<u32>().map_err(|_| x)) {
+		None => 0,
+		Some(Ok(x)) => x,
+		Some(Err(x)) => {
+			println!("padding must be a number of bytes. Found \"{}\"", x);
+			return Err(1);
+		}
+	};
+
+	let text_encoding = match matches.opt_str("text-encoding").as_deref() {
+		None | Some("latin1") => id3::TextEncoding::Latin1,
+		Some("utf16") => id3::TextEncoding::Utf16,
+		Some("utf16be") => id3::TextEncoding::Utf16BE,
+		Some("utf8") => id3::TextEncoding::Utf8,
+		Some(x) => {
+			println!("text-encoding must be latin1, utf16, utf16be or utf8. Found \"{}\"", x);
+			return Err(1);
+		}
+	};
+
+	let fix_encoding = match matches.opt_str("fix-encoding").as_deref() {
+		None => None,
+		Some("auto") => Some(Codepage::Detect),
+		Some(x) => match codepage::for_name(x) {
+			Some(codepage) => Some(Codepage::Fixed(codepage)),
+			None => {
+				println!("fix-encoding must be a codepage name or auto. Found \"{}\"", x);
+				return Err(1);
+			}
+		},
+	};
+
+	let rating = match matches
+		.opt_str("rating")
+		.map(|x| x.parse::<u8>().map_err(|_| x))
+		.transpose()
+	{
+		Ok(x) => x,
+		Err(x) => {
+			println!("rating must be between 0 and 255. Found \"{}\"", x);
+			return Err(1);
+		}
+	};
+
+	let play_count = match matches
+		.opt_str("play-count")
+		.map(|x| x.parse::<u64>().map_err(|_| x))
+		.transpose()
+	{
+		Ok(x) => x,
+		Err(x) => {
+			println!("play-count must be a non-negative number. Found \"{}\"", x);
+			return Err(1);
+		}
+	};
+
+	let mut replay_gain = ReplayGain::default();
+	for (name, value) in [
+		("track-gain", &mut replay_gain.track_gain),
+		("track-peak", &mut replay_gain.track_peak),
+		("album-gain", &mut replay_gain.album_gain),
+		("album-peak", &mut replay_gain.album_peak),
+	] {
+		*value = match matches
+			.opt_str(name)
+			.map(|x| x.parse::<f32>().map_err(|_| x))
+			.transpose()
+		{
+			Ok(x) => x,
+			Err(x) => {
+				println!("{} must be a number. Found \"{}\"", name, x);
+				return Err(1);
+			}
+		};
+	}
+
+	// Any option other than these may change the tag
+	let extract_only = (matches.opt_present("extract-lrc") || matches.opt_present("extract-objects"))
+		&& args[1..].iter().filter_map(|arg| arg.strip_prefix("--")).all(|arg| {
+			let name = arg.split_once('=').map_or(arg, |(name, _)| name);
+			matches!(name, "extract-lrc" | "extract-objects" | "fix-encoding" | "output")
+		});
+
 	let flags = Flags {
 		title: matches.opt_str("title"),
-		artist: matches.opt_str("artist"),
+		artist: matches.opt_strs("artist"),
 		track: matches.opt_str("track"),
 		album: matches.opt_str("album"),
 		sort_album: matches.opt_str("sort-album"),
-		genre: matches.opt_str("genre"),
+		genre: matches.opt_strs("genre"),
 		record_date: matches.opt_str("record-date"),
 		comment: matches.opt_str("comment"),
 		combine_comments: matches.opt_defined("combine_comments"),
@@ -125,6 +382,103 @@ fn main() -> Result<(), i32> {
 			.split(';')
 			.map(String::from)
 			.collect(),
+		txxx: matches
+			.opt_strs("txxx")
+			.iter()
+			.map(|arg| match arg.split_once('=') {
+				Some((description, value)) => Ok((description.to_owned(), value.to_owned())),
+				None => {
+					println!("txxx flag format must be {{description}}={{value}}. Found {}", arg);
+					Err(1)
+				}
+			})
+			.collect::<Result<Vec<_>, i32>>()?,
+		remove_txxx: matches.opt_strs("remove-txxx").into_iter().collect(),
+		urls: matches
+			.opt_strs("url")
+			.iter()
+			.map(|arg| {
+				let (id, url) = match arg.split_once('=') {
+					Some(x) => x,
+					None => {
+						println!("url flag format must be {{frame}}={{url}}. Found {}", arg);
+						return Err(1);
+					}
+				};
+				match id {
+					"WCOM" | "WCOP" | "WOAF" | "WOAR" | "WOAS" | "WORS" | "WPAY" | "WPUB" => {
+						Ok((id.as_bytes().try_into().unwrap(), url.to_owned()))
+					}
+					_ => {
+						println!("url flag frame is invalid. Found \"{}\"", id);
+						Err(1)
+					}
+				}
+			})
+			.collect::<Result<Vec<_>, i32>>()?,
+		wxxx: matches
+			.opt_strs("wxxx")
+			.iter()
+			.map(|arg| match arg.split_once('=') {
+				Some((description, url)) => Ok((description.to_owned(), url.to_owned())),
+				None => {
+					println!("wxxx flag format must be {{description}}={{url}}. Found {}", arg);
+					Err(1)
+				}
+			})
+			.collect::<Result<Vec<_>, i32>>()?,
+		ufid: matches
+			.opt_strs("ufid")
+			.iter()
+			.map(|arg| match arg.rsplit_once('=') {
+				Some((owner, identifier)) => Ok((owner.to_owned(), identifier.to_owned())),
+				None => {
+					println!("ufid flag format must be {{owner}}={{identifier}}. Found {}", arg);
+					Err(1)
+				}
+			})
+			.collect::<Result<Vec<_>, i32>>()?,
+		remove_ufid: matches.opt_strs("remove-ufid").into_iter().collect(),
+		rating,
+		play_count,
+		replay_gain,
+		lrc: matches.opt_str("lrc"),
+		extract_lrc: matches.opt_str("extract-lrc").map(PathBuf::from),
+		chapters: matches.opt_str("chapters"),
+		objects: matches
+			.opt_strs("object")
+			.iter()
+			.map(|arg| {
+				let mut split = arg.splitn(3, ':');
+				match (split.next(), split.next(), split.next()) {
+					(Some(mime), Some(description), Some(path)) if !path.is_empty() => Ok(ObjectArg {
+						mime: mime.to_owned(),
+						description: description.to_owned(),
+						path: path.to_owned(),
+					}),
+					_ => {
+						println!(
+							"object flag format must be {{mime type}}:{{description}}:{{path}}. Found {}",
+							arg
+						);
+						Err(1)
+					}
+				}
+			})
+			.collect::<Result<Vec<_>, i32>>()?,
+		extract_objects: matches.opt_str("extract-objects").map(PathBuf::from),
+		extract_only,
+		id3_version,
+		unsynchronise: matches.opt_present("unsynchronise"),
+		crc: matches.opt_present("crc"),
+		merge_id3v1: matches.opt_present("merge-id3v1"),
+		write_id3v1: matches.opt_present("write-id3v1"),
+		strip_ape: matches.opt_present("strip-ape"),
+		import_ape: matches.opt_present("import-ape"),
+		tlen: matches.opt_present("tlen"),
+		padding,
+		text_encoding,
+		fix_encoding,
 		out_path: Path::new(&out_path).to_path_buf(),
 	};
 
@@ -164,20 +518,25 @@ fn main() -> Result<(), i32> {
 	let mut paths: Vec<DirEntry> = files.map(|r| r.unwrap()).collect();
 
 	paths.sort_by_key(|dir| dir.path());
+	let mut failed = false;
 	for path in paths {
 		if let Err(e) = recode_path(&path.path(), &flags) {
-			println!("Error on {}: {}", path.file_name().to_str().unwrap(), e);
-			return Err(1);
+			println!("Error on {}: {}", path.file_name().to_string_lossy(), e);
+			println!("Skipping {}", path.path().display());
+			failed = true;
 		}
 	}
 
+	if failed {
+		return Err(1);
+	}
 	Ok(())
 }
 
 fn recode_path(path: &Path, flags: &Flags) -> Result<(), String> {
 	if path.file_name().unwrap_or_default().to_string_lossy().ends_with(".mp3") {
 		recode_mp3_file(path, flags)?;
-	} else if path.file_name().unwrap_or_default().to_string_lossy().ends_with(".m4a") {
+	} else if path.file_name().unwrap_or_default().to_string_lossy().ends_with(".m4a") && !flags.extract_only {
 		recode_m4a_file(path, flags.clone())?;
 	} else {
 		println!("Skipping {}", path.display());
@@ -272,14 +631,17 @@ fn recode_m4a_file(path: &Path, cmd_flags: Flags) -> Result<(), String> {
 	};
 	let ilst = mp4::ItemListConfig {
 		title: cmd_flags.title,
-		artist: cmd_flags.artist.clone(),
-		album_artist: cmd_flags.artist,
+		// MP4 items hold a single string
+		artist: join_values(&cmd_flags.artist),
+		album_artist: join_values(&cmd_flags.artist),
 		track: cmd_flags.track.map(|track| track.parse::<u32>().unwrap()),
 		album: cmd_flags.album,
 		sort_album: cmd_flags.sort_album,
-		genre: cmd_flags.genre,
+		genre: join_values(&cmd_flags.genre),
 		record_date: cmd_flags.record_date,
 		comment: cmd_flags.comment,
+		rating: cmd_flags.rating,
+		replay_gain: cmd_flags.replay_gain,
 		// combine_comments: cmd_flags.combine_comments,
 		// remove: cmd_flags.remove,
 	};
@@ -528,14 +890,23 @@ fn recode_m4a_file(path: &Path, cmd_flags: Flags) -> Result<(), String> {
 // 	s.into()
 // }
 
-fn move_text_item(new_list: &mut Vec<ID3Frame>, old_list: &mut Vec<ID3Frame>, code: [u8; 4], opt: &Option<String>) {
-	if let Some(item) = opt {
+fn join_values(values: &[String]) -> Option<String> {
+	if values.is_empty() {
+		None
+	} else {
+		Some(values.join("; "))
+	}
+}
+
+fn move_text_item(new_list: &mut Vec<ID3Frame>, old_list: &mut Vec<ID3Frame>, code: [u8; 4], values: &[String]) {
+	if !values.is_empty() {
 		new_list.push(id3::ID3Frame {
 			id: code,
-			flags: [0, 0],
+			flags: id3::ID3FrameFlags::default(),
 			data: id3::ID3FrameType::Text(id3::ID3TextFrame {
-				data: item.clone(),
-				encoding: if item.chars().all(|c| c.is_ascii()) { 0 } else { 3 },
+				values: values.to_vec(),
+				// Chosen when written
+				encoding: 0,
 			}),
 		});
 		old_list.retain(|f| f.id != code);
@@ -545,14 +916,135 @@ fn move_text_item(new_list: &mut Vec<ID3Frame>, old_list: &mut Vec<ID3Frame>, co
 }
 
 fn recode_mp3_file(path: &Path, cmd_flags: &Flags) -> Result<(), String> {
-	let content = match std::fs::read(path) {
+	let mut file = match std::fs::File::open(path) {
 		Ok(s) => s,
 		Err(e) => {
 			return Err(format!("Could not open file: {}: {}", path.display(), e));
 		}
 	};
+	let file_len = match file.metadata() {
+		Ok(x) => x.len(),
+		Err(e) => {
+			return Err(format!("Could not open file: {}: {}", path.display(), e));
+		}
+	};
+
+	let read = match cmd_flags.fix_encoding {
+		Some(Codepage::Fixed(codepage)) => {
+			println!("Decoding ISO-8859-1 text as {}", codepage.name());
+			tag::read_id3_tag_in_codepage_from(&mut file, codepage)
+		}
+		_ => tag::read_id3_tag_from(&mut file),
+	};
+	let (mut frames, id3_size) = match read {
+		Ok(tag) => {
+			for frame in &tag.unconverted {
+				println!(
+					"Dropping frame which could not be converted to ID3v2.4: {}",
+					frame.display()
+				);
+			}
+			let id3_size = tag.size();
+			(tag.frames, id3_size)
+		}
+		Err(tag::TagError::MissingHeader) => (Vec::new(), 0),
+		Err(e) => {
+			return Err(format!("Could not read ID3 tag: {}: {}", path.display(), e));
+		}
+	};
+	if let Some(Codepage::Detect) = cmd_flags.fix_encoding {
+		match codepage::detect(&frames) {
+			Some(codepage) => {
+				println!("Decoding ISO-8859-1 text as {}", codepage.name());
+				codepage::decode_frames(&mut frames, codepage);
+			}
+			None => println!("No legacy codepage detected"),
+		}
+	}
+
+	let id3v1 = match ID3v1Tag::read_from(&mut file) {
+		Ok(x) => x,
+		Err(e) => {
+			return Err(format!("Could not read ID3v1 tag: {}: {}", path.display(), e));
+		}
+	};
+	if cmd_flags.merge_id3v1 && frames.is_empty() {
+		if let Some(v1) = &id3v1 {
+			frames = v1.frames();
+		}
+	}
+
+	let ape_end = file_len - id3v1.as_ref().map_or(0, |v1| v1.size() as u64);
+	let ape = match APETag::read_from(&mut file, ape_end) {
+		Ok(x) => x,
+		Err(e) if cmd_flags.strip_ape || cmd_flags.import_ape => {
+			return Err(format!("Could not read APE tag: {}: {}", path.display(), e));
+		}
+		Err(e) => {
+			// Kept with the audio, as it isn't being changed
+			println!("Ignoring APE tag which could not be read: {}: {}", path.display(), e);
+			None
+		}
+	};
+	if cmd_flags.import_ape {
+		if let Some((ape, _)) = &ape {
+			let keys: HashSet<_> = frames.iter().map(frame_key).collect();
+			for item in &ape.items {
+				match item.frame() {
+					Some(frame) if !keys.contains(&frame_key(&frame)) => {
+						println!("Importing APE item: {}", item.key);
+						frames.push(frame);
+					}
+					Some(_) => println!("Skipping APE item already in ID3 tag: {}", item.key),
+					None => println!("Skipping APE item: {}", item.key),
+				}
+			}
+		}
+	}
+
+	// Omit the old ID3v1 tag, and the APE tag before it if asked to
+	let audio_end = match ape {
+		Some((_, ape_start)) if cmd_flags.strip_ape || cmd_flags.import_ape => ape_start,
+		_ => ape_end,
+	};
+	let audio = if cmd_flags.tlen || cmd_flags.chapters.is_some() {
+		let stream_end = ape.as_ref().map_or(ape_end, |(_, ape_start)| *ape_start);
+		match AudioProperties::read_from(&mut file, id3_size as u64, stream_end) {
+			Ok(x) => x,
+			Err(e) => {
+				return Err(format!("Could not read audio: {}: {}", path.display(), e));
+			}
+		}
+	} else {
+		None
+	};
 
-	let (mut frames, id3_size) = tag::read_id3_frames(&content);
+	if let Some(lrc_path) = &cmd_flags.extract_lrc {
+		// Prefer lyrics over other kinds of timed text
+		let sylt = frames
+			.iter()
+			.filter_map(|frame| match &frame.data {
+				id3::ID3FrameType::SyncLyrics(f) if f.timestamp_format == 2 => Some(f),
+				_ => None,
+			})
+			.min_by_key(|f| f.content_type != 1);
+		let sylt = match sylt {
+			Some(x) => x,
+			None => {
+				return Err(format!("No SYLT frame with millisecond timestamps: {}", path.display()));
+			}
+		};
+		if let Err(e) = std::fs::write(lrc_path, tag::lrc::to_lrc(&sylt.lyrics)) {
+			return Err(format!("Could not write LRC file: {}: {}", lrc_path.display(), e));
+		}
+	}
+
+	if let Some(dir) = &cmd_flags.extract_objects {
+		extract_objects(&frames, dir)?;
+	}
+	if cmd_flags.extract_only {
+		return Ok(());
+	}
 
 	frames.retain(|frame| {
 		if cmd_flags.remove.contains(String::from_utf8_lossy(&frame.id).as_ref()) {
@@ -563,39 +1055,55 @@ fn recode_mp3_file(path: &Path, cmd_flags: &Flags) -> Result<(), String> {
 	});
 
 	{
-		// Check for more than one frame of the same type
+		// Check for more than one frame of the same type. Those which may repeat are told apart by `frame_key`.
 		let mut seen = HashSet::new();
 		for frame in &frames {
-			if seen.contains(&frame.id) {
+			let key = frame_key(frame);
+			if seen.contains(&key) {
 				if frame.id == *b"COMM" && (cmd_flags.comment.is_some() || cmd_flags.combine_comments) {
 					// Skip
+				} else if frame.id == *b"WCOM" || frame.id == *b"WOAR" {
+					// There may be one of each for several URLs
+				} else if let id3::ID3FrameType::Raw(_) = frame.data {
+					// Frames we don't understand may legitimately repeat
 				} else {
-					panic!("More than one frame containing {}", String::from_utf8_lossy(&frame.id));
+					return Err(format!(
+						"More than one frame containing {}: {}",
+						String::from_utf8_lossy(&frame.id),
+						path.display()
+					));
 				}
 			}
-			seen.insert(frame.id);
+			seen.insert(key);
 		}
 	}
 
 	let mut new_frames = Vec::with_capacity(frames.len());
 
-	move_text_item(&mut new_frames, &mut frames, b"TIT2".to_owned(), &cmd_flags.title);
+	move_text_item(
+		&mut new_frames,
+		&mut frames,
+		b"TIT2".to_owned(),
+		cmd_flags.title.as_slice(),
+	);
 
-	if let Some(artist) = &cmd_flags.artist {
+	if !cmd_flags.artist.is_empty() {
 		new_frames.push(id3::ID3Frame {
 			id: b"TPE1".to_owned(),
-			flags: [0, 0],
+			flags: id3::ID3FrameFlags::default(),
 			data: id3::ID3FrameType::Text(id3::ID3TextFrame {
-				data: artist.clone(),
-				encoding: if artist.chars().all(|c| c.is_ascii()) { 0 } else { 3 },
+				values: cmd_flags.artist.clone(),
+				// Chosen when written
+				encoding: 0,
 			}),
 		});
 		new_frames.push(id3::ID3Frame {
 			id: b"TPE2".to_owned(),
-			flags: [0, 0],
+			flags: id3::ID3FrameFlags::default(),
 			data: id3::ID3FrameType::Text(id3::ID3TextFrame {
-				data: artist.clone(),
-				encoding: if artist.chars().all(|c| c.is_ascii()) { 0 } else { 3 },
+				values: cmd_flags.artist.clone(),
+				// Chosen when written
+				encoding: 0,
 			}),
 		});
 		if let Some(ix) = frames.iter().position(|f| &f.id == b"TPE1") {
@@ -613,18 +1121,34 @@ fn recode_mp3_file(path: &Path, cmd_flags: &Flags) -> Result<(), String> {
 		}
 	}
 
-	move_text_item(&mut new_frames, &mut frames, b"TRCK".to_owned(), &cmd_flags.track);
-	move_text_item(&mut new_frames, &mut frames, b"TALB".to_owned(), &cmd_flags.album);
-	move_text_item(&mut new_frames, &mut frames, b"TSOA".to_owned(), &cmd_flags.sort_album);
+	move_text_item(
+		&mut new_frames,
+		&mut frames,
+		b"TRCK".to_owned(),
+		cmd_flags.track.as_slice(),
+	);
+	move_text_item(
+		&mut new_frames,
+		&mut frames,
+		b"TALB".to_owned(),
+		cmd_flags.album.as_slice(),
+	);
+	move_text_item(
+		&mut new_frames,
+		&mut frames,
+		b"TSOA".to_owned(),
+		cmd_flags.sort_album.as_slice(),
+	);
 	move_text_item(&mut new_frames, &mut frames, b"TCON".to_owned(), &cmd_flags.genre);
 
 	if let Some(item) = &cmd_flags.record_date {
 		new_frames.push(id3::ID3Frame {
 			id: b"TDRC".to_owned(),
-			flags: [0, 0],
+			flags: id3::ID3FrameFlags::default(),
 			data: id3::ID3FrameType::Text(id3::ID3TextFrame {
-				data: item.clone(),
-				encoding: if item.chars().all(|c| c.is_ascii()) { 0 } else { 3 },
+				values: vec![item.clone()],
+				// Chosen when written
+				encoding: 0,
 			}),
 		});
 		if let Some(ix) = frames.iter().position(|f| &f.id == b"TDRC") {
@@ -633,13 +1157,15 @@ fn recode_mp3_file(path: &Path, cmd_flags: &Flags) -> Result<(), String> {
 	} else if let Some(ix) = frames.iter().position(|f| &f.id == b"TDRC") {
 		let mut frame = frames.remove(ix);
 		if let id3::ID3FrameType::Text(tf) = &mut frame.data {
-			let parts: Vec<&str> = tf.data.split(';').collect();
-			let date = parts[0].replace('.', "-");
-			if parts.len() > 1 {
-				let time = parts[1].replace('.', ":");
-				tf.data = date + "T" + &time;
-			} else {
-				tf.data = date;
+			for value in tf.values.iter_mut() {
+				let parts: Vec<&str> = value.split(';').collect();
+				let date = parts[0].replace('.', "-");
+				if parts.len() > 1 {
+					let time = parts[1].replace('.', ":");
+					*value = date + "T" + &time;
+				} else {
+					*value = date;
+				}
 			}
 		} else {
 			panic!("TDRC frame is not text");
@@ -650,12 +1176,13 @@ fn recode_mp3_file(path: &Path, cmd_flags: &Flags) -> Result<(), String> {
 	if let Some(comment) = &cmd_flags.comment {
 		new_frames.push(id3::ID3Frame {
 			id: b"COMM".to_owned(),
-			flags: [0, 0],
+			flags: id3::ID3FrameFlags::default(),
 			data: id3::ID3FrameType::Comment(id3::ID3CommentFrame {
 				language: [b'e', b'n', b'g'], // eng
 				content_desc: String::new(),
 				text: comment.clone(),
-				encoding: if comment.chars().all(|c| c.is_ascii()) { 0 } else { 3 },
+				// Chosen when written
+				encoding: 0,
 			}),
 		});
 		frames.retain(|f| &f.id != b"COMM");
@@ -678,19 +1205,15 @@ fn recode_mp3_file(path: &Path, cmd_flags: &Flags) -> Result<(), String> {
 		}
 		for comment in comments {
 			let (content_desc, text) = comment;
-			let encoding = if text.chars().all(|c| c.is_ascii()) && content_desc.chars().all(|c| c.is_ascii()) {
-				0
-			} else {
-				3
-			};
 			new_frames.push(ID3Frame {
 				id: b"COMM".to_owned(),
-				flags: [0, 0],
+				flags: id3::ID3FrameFlags::default(),
 				data: id3::ID3FrameType::Comment(ID3CommentFrame {
 					language: b"eng".to_owned(),
 					content_desc,
 					text,
-					encoding,
+					// Chosen when written
+					encoding: 0,
 				}),
 			});
 		}
@@ -707,7 +1230,7 @@ fn recode_mp3_file(path: &Path, cmd_flags: &Flags) -> Result<(), String> {
 		};
 		new_frames.push(ID3Frame {
 			id: b"APIC".to_owned(),
-			flags: [0, 0],
+			flags: id3::ID3FrameFlags::default(),
 			data: id3::ID3FrameType::Picture(ID3PictureFrame {
 				mime: pic.mime.clone(),
 				pic_type: pic.typ,
@@ -721,69 +1244,468 @@ fn recode_mp3_file(path: &Path, cmd_flags: &Flags) -> Result<(), String> {
 		frames.retain(|frame| &frame.id != b"APIC");
 	}
 
+	if let Some(lrc_path) = &cmd_flags.lrc {
+		let lrc = match std::fs::read_to_string(lrc_path) {
+			Ok(x) => x,
+			Err(e) => {
+				return Err(format!("Error reading LRC path {}: {}", lrc_path, e));
+			}
+		};
+		new_frames.push(ID3Frame {
+			id: b"SYLT".to_owned(),
+			flags: id3::ID3FrameFlags::default(),
+			data: id3::ID3FrameType::SyncLyrics(id3::ID3SyncLyricsFrame {
+				language: b"eng".to_owned(),
+				timestamp_format: 2, // Milliseconds
+				content_type: 1,     // Lyrics
+				description: String::new(),
+				lyrics: tag::lrc::from_lrc(&lrc),
+				// Chosen when written
+				encoding: 0,
+			}),
+		});
+		frames.retain(|frame| &frame.id != b"SYLT");
+	}
+
+	frames.retain(|frame| match &frame.data {
+		id3::ID3FrameType::UserText(f) => {
+			if cmd_flags.remove_txxx.contains(&f.description) {
+				println!("Dropping frame: {}", frame.display());
+				return false;
+			}
+			!cmd_flags
+				.txxx
+				.iter()
+				.any(|(description, _)| description == &f.description)
+		}
+		_ => true,
+	});
+
+	for (description, value) in &cmd_flags.txxx {
+		// Repeating a description adds another value to the same frame
+		let existing = new_frames.iter_mut().find_map(|frame| match &mut frame.data {
+			id3::ID3FrameType::UserText(f) if &f.description == description => Some(f),
+			_ => None,
+		});
+		if let Some(f) = existing {
+			f.values.push(value.clone());
+			continue;
+		}
+		new_frames.push(ID3Frame {
+			id: b"TXXX".to_owned(),
+			flags: id3::ID3FrameFlags::default(),
+			data: id3::ID3FrameType::UserText(id3::ID3UserTextFrame {
+				description: description.clone(),
+				values: vec![value.clone()],
+				// Chosen when written
+				encoding: 0,
+			}),
+		});
+	}
+
+	frames.retain(|frame| match &frame.data {
+		id3::ID3FrameType::Url(_) => !cmd_flags.urls.iter().any(|(id, _)| *id == frame.id),
+		id3::ID3FrameType::UserUrl(f) => !cmd_flags
+			.wxxx
+			.iter()
+			.any(|(description, _)| *description == f.description),
+		_ => true,
+	});
+
+	frames.retain(|frame| match &frame.data {
+		id3::ID3FrameType::UniqueFileId(f) => {
+			if cmd_flags.remove_ufid.contains(&f.owner) {
+				println!("Dropping frame: {}", frame.display());
+				return false;
+			}
+			!cmd_flags.ufid.iter().any(|(owner, _)| *owner == f.owner)
+		}
+		_ => true,
+	});
+
+	for object in &cmd_flags.objects {
+		let data = match std::fs::read(&object.path) {
+			Ok(x) => x,
+			Err(e) => {
+				return Err(format!("Error reading object path {}: {}", object.path, e));
+			}
+		};
+		frames.retain(
+			|frame| !matches!(&frame.data, id3::ID3FrameType::GeneralObject(f) if f.description == object.description),
+		);
+		new_frames.push(ID3Frame {
+			id: b"GEOB".to_owned(),
+			flags: id3::ID3FrameFlags::default(),
+			data: id3::ID3FrameType::GeneralObject(id3::ID3GeneralObjectFrame {
+				mime: object.mime.clone(),
+				filename: Path::new(&object.path)
+					.file_name()
+					.map(|x| x.to_string_lossy().into_owned())
+					.unwrap_or_default(),
+				description: object.description.clone(),
+				data,
+				// Chosen when written
+				encoding: 0,
+				filename_bom: id3::ByteOrderMark::default(),
+				description_bom: id3::ByteOrderMark::default(),
+			}),
+		});
+	}
+
+	if let Some(chapters_path) = &cmd_flags.chapters {
+		let text = match std::fs::read_to_string(chapters_path) {
+			Ok(x) => x,
+			Err(e) => {
+				return Err(format!("Error reading chapters path {}: {}", chapters_path, e));
+			}
+		};
+		new_frames.extend(chapter_frames(&text, audio.as_ref().map(|a| a.duration_ms() as u32))?);
+		frames.retain(|frame| &frame.id != b"CHAP" && &frame.id != b"CTOC");
+	}
+
+	if !cmd_flags.replay_gain.is_empty() {
+		// Values which aren't being set are kept, and all locations are rewritten to agree
+		let replay_gain = cmd_flags.replay_gain.or(ReplayGain::from_id3(&frames));
+		frames.retain(|frame| !replaygain::is_replay_gain_frame(frame));
+		new_frames.extend(replay_gain.id3_frames());
+	}
+
+	if cmd_flags.rating.is_some() || cmd_flags.play_count.is_some() {
+		// Update the existing popularimeter rather than adding one for another email
+		let popm = frames.iter_mut().find_map(|frame| match &mut frame.data {
+			id3::ID3FrameType::Popularimeter(f) => Some(f),
+			_ => None,
+		});
+		match popm {
+			Some(f) => {
+				if let Some(rating) = cmd_flags.rating {
+					f.rating = rating;
+				}
+				if cmd_flags.play_count.is_some() {
+					f.counter = cmd_flags.play_count;
+				}
+			}
+			None => {
+				if let Some(rating) = cmd_flags.rating {
+					new_frames.push(ID3Frame {
+						id: b"POPM".to_owned(),
+						flags: id3::ID3FrameFlags::default(),
+						data: id3::ID3FrameType::Popularimeter(id3::ID3PopularimeterFrame {
+							email: String::new(),
+							rating,
+							counter: cmd_flags.play_count,
+						}),
+					});
+				}
+			}
+		}
+	}
+
+	if let Some(counter) = cmd_flags.play_count {
+		new_frames.push(ID3Frame {
+			id: b"PCNT".to_owned(),
+			flags: id3::ID3FrameFlags::default(),
+			data: id3::ID3FrameType::PlayCounter(id3::ID3PlayCounterFrame { counter }),
+		});
+		frames.retain(|frame| &frame.id != b"PCNT");
+	}
+
+	for (owner, identifier) in &cmd_flags.ufid {
+		new_frames.push(ID3Frame {
+			id: b"UFID".to_owned(),
+			flags: id3::ID3FrameFlags::default(),
+			data: id3::ID3FrameType::UniqueFileId(id3::ID3UniqueFileIdFrame {
+				owner: owner.clone(),
+				identifier: identifier.as_bytes().to_vec(),
+			}),
+		});
+	}
+
+	for (id, url) in &cmd_flags.urls {
+		new_frames.push(ID3Frame {
+			id: *id,
+			flags: id3::ID3FrameFlags::default(),
+			data: id3::ID3FrameType::Url(id3::ID3UrlFrame { url: url.clone() }),
+		});
+	}
+
+	for (description, url) in &cmd_flags.wxxx {
+		new_frames.push(ID3Frame {
+			id: b"WXXX".to_owned(),
+			flags: id3::ID3FrameFlags::default(),
+			data: id3::ID3FrameType::UserUrl(id3::ID3UserUrlFrame {
+				description: description.clone(),
+				url: url.clone(),
+				// Chosen when written
+				encoding: 0,
+			}),
+		});
+	}
+
+	if cmd_flags.tlen {
+		let duration = match &audio {
+			Some(x) => x.duration_ms(),
+			None => {
+				return Err(format!("No MPEG audio found: {}", path.display()));
+			}
+		};
+		frames.retain(|frame| &frame.id != b"TLEN");
+		new_frames.push(ID3Frame {
+			id: b"TLEN".to_owned(),
+			flags: id3::ID3FrameFlags::default(),
+			data: id3::ID3FrameType::Text(id3::ID3TextFrame {
+				values: vec![duration.to_string()],
+				encoding: 0,
+			}),
+		});
+	}
+
 	for frame in frames {
 		println!("Remaining frame: {}", frame.display());
 		new_frames.push(frame);
 	}
 
-	let new_size = new_frames
-		.iter()
-		.fold(0u32, |accum, frame| -> u32 { accum + 10 + frame.data.len() as u32 });
-	// println!("Final size: {}", new_size);
+	// ID3v2.3 has no footer
+	let do_foot = cmd_flags.id3_version == 4;
+	let mut header_flags = if do_foot { 0b0001_0000 } else { 0 };
+	if cmd_flags.unsynchronise {
+		header_flags |= 0b1000_0000;
+	}
 
-	let do_foot = true;
+	let new_id3v1 = if cmd_flags.write_id3v1 {
+		Some(ID3v1Tag::from_frames(&new_frames))
+	} else {
+		None
+	};
 
-	let id3 = id3::ID3v240Tag {
+	let mut id3 = id3::ID3v240Tag {
 		header: id3::ID3Header {
-			version_major: 4,
+			version_major: cmd_flags.id3_version,
 			version_minor: 0,
-			flags: if do_foot { 0b0001_0000 } else { 0 },
-			size: new_size,
+			flags: header_flags,
+			// Calculated when written
+			size: 0,
+		},
+		extended_header: if cmd_flags.crc {
+			Some(id3::ID3ExtendedHeader {
+				// Calculated when written
+				crc: Some(0),
+				..Default::default()
+			})
+		} else {
+			None
 		},
-		extended_header: None,
 		frames: new_frames,
-		padding: 0,
+		unconverted: Vec::new(),
+		padding: cmd_flags.padding,
 		has_footer: do_foot,
+		text_encoding: cmd_flags.text_encoding,
 	};
 
-	let mut f = match std::fs::File::create(&cmd_flags.out_path) {
+	let replacing = is_same_file(path, &cmd_flags.out_path);
+	if id3_size > 0 && id3_size as u64 <= audio_end && replacing && id3.pad_to(id3_size) {
+		println!("Rewriting tag in place");
+		return write_in_place(
+			&cmd_flags.out_path,
+			&id3.bytes(),
+			audio_end,
+			new_id3v1.map(|v1| v1.bytes()).unwrap_or_default(),
+		);
+	}
+
+	// When replacing the input the audio is still to be read from it, so write alongside and rename
+	let write_path = if replacing {
+		let name = cmd_flags.out_path.file_name().unwrap_or_default().to_string_lossy();
+		cmd_flags.out_path.with_file_name(format!(".{}.tmp", name))
+	} else {
+		cmd_flags.out_path.clone()
+	};
+	let mut f = match std::fs::File::create(&write_path) {
 		Ok(x) => x,
 		Err(e) => {
+			return Err(format!("Could not create file: {}: {}", write_path.display(), e));
+		}
+	};
+
+	let written = f
+		.write_all(&id3.bytes())
+		.and_then(|_| tag::copy_range(&mut file, &mut f, (id3_size as u64).min(audio_end), audio_end))
+		.and_then(|_| f.write_all(&new_id3v1.map(|v1| v1.bytes()).unwrap_or_default()));
+	if let Err(e) = written {
+		if replacing {
+			let _ = std::fs::remove_file(&write_path);
+		}
+		return Err(format!("Error writing bytes: {}", e));
+	}
+
+	if replacing {
+		if let Err(e) = std::fs::rename(&write_path, &cmd_flags.out_path) {
 			return Err(format!(
-				"Could not create file: {}: {}",
+				"Could not replace file: {}: {}",
 				cmd_flags.out_path.display(),
 				e
 			));
 		}
-	};
+	}
 
-	match f.write_all(&id3.bytes()) {
-		Ok(_) => (),
-		Err(e) => {
-			return Err(format!("Error writing bytes: {}", e));
-		}
-	};
+	Ok(())
+}
 
-	// Omit ID3v1 tag
-	let last_idx = content.len();
-	let has_id3v1_tag =
-		content[last_idx - 128] == b'T' && content[1 + last_idx - 128] == b'A' && content[2 + last_idx - 128] == b'G';
-	let mp3_byte_range = if has_id3v1_tag {
-		&content[id3_size..last_idx - 128]
-	} else {
-		&content[id3_size..]
-	};
+fn is_same_file(a: &Path, b: &Path) -> bool {
+	match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+		(Ok(a), Ok(b)) => a == b,
+		_ => false,
+	}
+}
 
-	match f.write_all(mp3_byte_range) {
-		Ok(_) => (),
+/// Overwrite the ID3v2 tag at the start of the file at `path` with `tag`, which must be the same size, leaving the
+/// audio alone. Everything from `audio_end` is replaced with `tail`.
+fn write_in_place(path: &Path, tag: &[u8], audio_end: u64, tail: Vec<u8>) -> Result<(), String> {
+	let mut f = match std::fs::OpenOptions::new().write(true).open(path) {
+		Ok(x) => x,
 		Err(e) => {
-			return Err(format!("Error writing bytes: {}", e));
+			return Err(format!("Could not open file: {}: {}", path.display(), e));
 		}
 	};
+	let written = f
+		.write_all(tag)
+		.and_then(|_| f.seek(SeekFrom::Start(audio_end)))
+		.and_then(|_| f.write_all(&tail))
+		.and_then(|_| f.set_len(audio_end + tail.len() as u64));
+	match written {
+		Ok(_) => Ok(()),
+		Err(e) => Err(format!("Error writing bytes: {}", e)),
+	}
+}
 
+/// Frames of a type which may appear more than once in a tag are told apart by their description, picture type,
+/// language, element ID or owner.
+fn frame_key(frame: &ID3Frame) -> ([u8; 4], String) {
+	match &frame.data {
+		id3::ID3FrameType::Picture(f) => (frame.id, format!("{}:{}", f.pic_type, f.description)),
+		// COMM and USLT
+		id3::ID3FrameType::Comment(f) => (
+			frame.id,
+			format!("{}:{}", String::from_utf8_lossy(&f.language), f.content_desc),
+		),
+		id3::ID3FrameType::SyncLyrics(f) => (
+			frame.id,
+			format!("{}:{}", String::from_utf8_lossy(&f.language), f.description),
+		),
+		id3::ID3FrameType::UserText(f) => (frame.id, f.description.clone()),
+		id3::ID3FrameType::UserUrl(f) => (frame.id, f.description.clone()),
+		id3::ID3FrameType::Chapter(f) => (frame.id, f.element_id.clone()),
+		id3::ID3FrameType::TableOfContents(f) => (frame.id, f.element_id.clone()),
+		// UFID and PRIV are distinguished by their owner, GEOB by its description
+		data => (frame.id, data.owner().unwrap_or_default().to_owned()),
+	}
+}
+
+/// Write the data of each GEOB frame to `dir`, named by its filename. Objects without a usable filename are named by
+/// their position.
+fn extract_objects(frames: &[ID3Frame], dir: &Path) -> Result<(), String> {
+	let objects = frames.iter().filter_map(|frame| match &frame.data {
+		id3::ID3FrameType::GeneralObject(f) => Some(f),
+		_ => None,
+	});
+	let mut used = HashSet::new();
+	for (i, object) in objects.enumerate() {
+		// Only use the last component so the object can't be written outside the directory
+		let mut name = match Path::new(&object.filename).file_name() {
+			Some(x) => x.to_string_lossy().into_owned(),
+			None => format!("object{}.bin", i),
+		};
+		// Objects with the same name are told apart by their position
+		if used.contains(&name) {
+			name = format!("object{}-{}", i, name);
+		}
+		if !used.insert(name.clone()) {
+			return Err(format!(
+				"More than one object would be extracted to {}",
+				dir.join(&name).display()
+			));
+		}
+		let path = dir.join(name);
+		println!("Extracting {} to {}", object.description, path.display());
+		if let Err(e) = std::fs::write(&path, &object.data) {
+			return Err(format!("Could not write object: {}: {}", path.display(), e));
+		}
+	}
 	Ok(())
 }
 
+/// Build CHAP frames and a CTOC frame listing them from lines of "HH:MM:SS title". Each chapter ends where the next
+/// one starts, and the last at `duration` in milliseconds if known.
+fn chapter_frames(text: &str, duration: Option<u32>) -> Result<Vec<ID3Frame>, String> {
+	let mut chapters = Vec::<(u32, String)>::new();
+	for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+		let (time, title) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+		let parts = time
+			.split(':')
+			.map(|x| x.parse::<u32>().ok())
+			.collect::<Option<Vec<_>>>();
+		let seconds = match parts.as_deref() {
+			Some([hours, minutes, seconds]) if *minutes < 60 && *seconds < 60 => hours * 3600 + minutes * 60 + seconds,
+			_ => {
+				return Err(format!("Chapter line must be in format HH:MM:SS title. Found {}", line));
+			}
+		};
+		chapters.push((seconds * 1000, title.trim().to_owned()));
+	}
+	chapters.sort_by_key(|(start, _)| *start);
+	// The table of contents holds at most 255 chapters
+	if chapters.len() > u8::MAX as usize {
+		return Err(format!(
+			"Too many chapters: {}, at most {} can be imported",
+			chapters.len(),
+			u8::MAX
+		));
+	}
+
+	let mut ret = Vec::with_capacity(chapters.len() + 1);
+	let mut children = Vec::with_capacity(chapters.len());
+	for (i, (start_time, title)) in chapters.iter().enumerate() {
+		let element_id = format!("chp{}", i);
+		// If the length of the audio isn't known the last chapter ends where it starts
+		let end_time = chapters
+			.get(i + 1)
+			.map_or(duration.unwrap_or(*start_time).max(*start_time), |(next, _)| *next);
+		ret.push(ID3Frame {
+			id: b"CHAP".to_owned(),
+			flags: id3::ID3FrameFlags::default(),
+			data: id3::ID3FrameType::Chapter(id3::ID3ChapterFrame {
+				element_id: element_id.clone(),
+				start_time: *start_time,
+				end_time,
+				// Not used
+				start_offset: u32::MAX,
+				end_offset: u32::MAX,
+				frames: vec![ID3Frame {
+					id: b"TIT2".to_owned(),
+					flags: id3::ID3FrameFlags::default(),
+					data: id3::ID3FrameType::Text(id3::ID3TextFrame {
+						values: vec![title.clone()],
+						encoding: 0,
+					}),
+				}],
+			}),
+		});
+		children.push(element_id);
+	}
+	ret.push(ID3Frame {
+		id: b"CTOC".to_owned(),
+		flags: id3::ID3FrameFlags::default(),
+		data: id3::ID3FrameType::TableOfContents(id3::ID3TableOfContentsFrame {
+			element_id: String::from("toc"),
+			top_level: true,
+			ordered: true,
+			children,
+			frames: Vec::new(),
+		}),
+	});
+	Ok(ret)
+}
+
 fn match_pic_type(typ: &str) -> Option<u8> {
 	match typ {
 		"Other" => Some(0x00),
@@ -0,0 +1,177 @@
+use encoding_rs::{BIG5, GBK, SHIFT_JIS, WINDOWS_1251};
+
+pub use encoding_rs::Encoding;
+
+use crate::id3::{ID3Frame, ID3FrameType};
+
+/// The codepages `detect` chooses between. Where text could be in more than one of them the earlier one is chosen.
+pub const CODEPAGES: [&Encoding; 4] = [WINDOWS_1251, SHIFT_JIS, GBK, BIG5];
+
+/// The codepage with the given name or alias, such as "CP1251", "windows-1251", "Shift_JIS", "GBK" or "Big5".
+pub fn for_name(name: &str) -> Option<&'static Encoding> {
+	Encoding::for_label(name.as_bytes())
+}
+
+/// Decode the text of `frames` which is marked as ISO-8859-1 (encoding 0) as `codepage` instead, including the frames
+/// embedded in CHAP and CTOC frames. Bytes which aren't valid in the codepage become U+FFFD.
+///
+/// Reading ISO-8859-1 maps every byte to the character of the same value, so the text still holds the original bytes.
+/// APIC descriptions are left as they are since the frame doesn't keep its encoding.
+pub fn decode_frames(frames: &mut [ID3Frame], codepage: &'static Encoding) {
+	for s in latin1_strings_mut(frames) {
+		if let Some(bytes) = latin1_bytes(s) {
+			*s = codepage.decode_without_bom_handling(&bytes).0.into_owned();
+		}
+	}
+}
+
+/// Guess the codepage of the text of `frames` which is marked as ISO-8859-1. Returns None when the text is ASCII,
+/// looks like ISO-8859-1, or doesn't look like any of `CODEPAGES`.
+///
+/// Each codepage is scored by how many of the bytes it decodes to characters likely to be found in its languages, so a
+/// few words of text are needed for a reliable guess.
+pub fn detect(frames: &[ID3Frame]) -> Option<&'static Encoding> {
+	let strings: Vec<Vec<u8>> = latin1_strings(frames)
+		.into_iter()
+		.filter_map(latin1_bytes)
+		.filter(|bytes| !bytes.is_ascii())
+		.collect();
+	let total = strings.iter().flatten().filter(|b| !b.is_ascii()).count();
+	if total == 0 {
+		return None;
+	}
+
+	let mut best = (strings.iter().map(|s| latin1_score(s)).sum::<usize>(), None);
+	for codepage in CODEPAGES {
+		// A codepage which can't decode all of the text is ruled out
+		let score = match strings.iter().map(|s| score(s, codepage)).sum::<Option<usize>>() {
+			Some(x) => x,
+			None => continue,
+		};
+		if score > best.0 {
+			best = (score, Some(codepage));
+		}
+	}
+	// Most of the text should look right
+	if best.0 * 4 < total * 3 {
+		return None;
+	}
+	best.1
+}
+
+fn latin1_bytes(s: &str) -> Option<Vec<u8>> {
+	s.chars().map(|c| u8::try_from(c).ok()).collect()
+}
+
+/// The number of non-ASCII bytes which `codepage` decodes to likely characters, or None if they aren't valid in it.
+fn score(bytes: &[u8], codepage: &'static Encoding) -> Option<usize> {
+	let text = codepage.decode_without_bom_handling_and_without_replacement(bytes)?;
+	let chars: Vec<char> = text.chars().collect();
+	let mut ret = 0;
+	for (i, c) in chars.iter().enumerate() {
+		if c.is_ascii() {
+			continue;
+		}
+		let mut buf = [0; 4];
+		let encoded = codepage.encode(c.encode_utf8(&mut buf)).0;
+		let likely = if codepage == WINDOWS_1251 {
+			// Capitals start words, so one following a small letter is unlikely
+			let after_small = i > 0 && is_small_cyrillic(chars[i - 1]);
+			is_small_cyrillic(*c)
+				|| (is_capital_cyrillic(*c) && !after_small)
+				|| matches!(c, '«' | '»' | '–' | '—' | '№' | '…' | '“' | '”' | '„')
+		} else if codepage == SHIFT_JIS {
+			// Half-width katakana are single bytes, which most other text decodes to
+			matches!(c, '\u{3000}'..='\u{30FF}' | '\u{4E00}'..='\u{9FFF}' | '\u{FF01}'..='\u{FF5E}')
+		} else if codepage == GBK {
+			// GB2312, which holds the characters in everyday use
+			is_cjk(*c) && matches!(&encoded[..], [0xA1..=0xF7, 0xA1..=0xFE])
+		} else {
+			is_cjk(*c)
+		};
+		// Trailing bytes may be ASCII, but only the others count towards the total
+		if likely {
+			ret += encoded.iter().filter(|b| !b.is_ascii()).count();
+		}
+	}
+	Some(ret)
+}
+
+/// The number of bytes which are likely in ISO-8859-1 text: accented letters next to unaccented ones, and a few
+/// symbols.
+fn latin1_score(bytes: &[u8]) -> usize {
+	let is_letter = |b: u8| b.is_ascii_alphabetic();
+	let mut ret = 0;
+	for (i, b) in bytes.iter().enumerate() {
+		let beside_letter = (i > 0 && is_letter(bytes[i - 1])) || bytes.get(i + 1).is_some_and(|b| is_letter(*b));
+		let likely = match b {
+			0xC0..=0xFF if *b != 0xD7 && *b != 0xF7 => beside_letter,
+			0xA9 | 0xAE | 0xB0 | 0xA1 | 0xBF => true,
+			_ => false,
+		};
+		if likely {
+			ret += 1;
+		}
+	}
+	ret
+}
+
+fn is_small_cyrillic(c: char) -> bool {
+	('а'..='я').contains(&c) || c == 'ё'
+}
+
+fn is_capital_cyrillic(c: char) -> bool {
+	('А'..='Я').contains(&c) || c == 'Ё'
+}
+
+fn is_cjk(c: char) -> bool {
+	matches!(c, '\u{3000}'..='\u{303F}' | '\u{4E00}'..='\u{9FFF}' | '\u{FF01}'..='\u{FF5E}')
+}
+
+fn latin1_strings(frames: &[ID3Frame]) -> Vec<&str> {
+	let mut ret = Vec::new();
+	for frame in frames {
+		match &frame.data {
+			ID3FrameType::Text(f) if f.encoding == 0 => ret.extend(f.values.iter().map(|v| v.as_str())),
+			ID3FrameType::UserText(f) if f.encoding == 0 => {
+				ret.push(&f.description);
+				ret.extend(f.values.iter().map(|v| v.as_str()));
+			}
+			ID3FrameType::UserUrl(f) if f.encoding == 0 => ret.push(&f.description),
+			ID3FrameType::Comment(f) if f.encoding == 0 => ret.extend([f.content_desc.as_str(), &f.text]),
+			ID3FrameType::SyncLyrics(f) if f.encoding == 0 => {
+				ret.push(&f.description);
+				ret.extend(f.lyrics.iter().map(|(text, _)| text.as_str()));
+			}
+			ID3FrameType::GeneralObject(f) if f.encoding == 0 => ret.extend([f.filename.as_str(), &f.description]),
+			ID3FrameType::Chapter(f) => ret.extend(latin1_strings(&f.frames)),
+			ID3FrameType::TableOfContents(f) => ret.extend(latin1_strings(&f.frames)),
+			_ => {}
+		}
+	}
+	ret
+}
+
+fn latin1_strings_mut(frames: &mut [ID3Frame]) -> Vec<&mut String> {
+	let mut ret = Vec::new();
+	for frame in frames {
+		match &mut frame.data {
+			ID3FrameType::Text(f) if f.encoding == 0 => ret.extend(f.values.iter_mut()),
+			ID3FrameType::UserText(f) if f.encoding == 0 => {
+				ret.push(&mut f.description);
+				ret.extend(f.values.iter_mut());
+			}
+			ID3FrameType::UserUrl(f) if f.encoding == 0 => ret.push(&mut f.description),
+			ID3FrameType::Comment(f) if f.encoding == 0 => ret.extend([&mut f.content_desc, &mut f.text]),
+			ID3FrameType::SyncLyrics(f) if f.encoding == 0 => {
+				ret.push(&mut f.description);
+				ret.extend(f.lyrics.iter_mut().map(|(text, _)| text));
+			}
+			ID3FrameType::GeneralObject(f) if f.encoding == 0 => ret.extend([&mut f.filename, &mut f.description]),
+			ID3FrameType::Chapter(f) => ret.extend(latin1_strings_mut(&mut f.frames)),
+			ID3FrameType::TableOfContents(f) => ret.extend(latin1_strings_mut(&mut f.frames)),
+			_ => {}
+		}
+	}
+	ret
+}
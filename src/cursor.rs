@@ -0,0 +1,169 @@
+use crate::TagError;
+
+/// Reads through a byte slice, checking every read against the end of it so that no input can cause a panic.
+///
+/// Errors carry the offset at which the failed read started, relative to the start of the slice.
+#[derive(Clone)]
+pub(crate) struct Cursor<'a> {
+	content: &'a [u8],
+	ix: usize,
+}
+
+impl<'a> Cursor<'a> {
+	pub fn new(content: &'a [u8]) -> Self {
+		Cursor { content, ix: 0 }
+	}
+
+	/// A cursor starting at `ix`, which is clamped to the end of `content`
+	pub fn at(content: &'a [u8], ix: usize) -> Self {
+		Cursor {
+			content,
+			ix: ix.min(content.len()),
+		}
+	}
+
+	pub fn offset(&self) -> usize {
+		self.ix
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.ix >= self.content.len()
+	}
+
+	/// The next byte, without moving past it
+	pub fn peek(&self) -> Option<u8> {
+		self.content.get(self.ix).copied()
+	}
+
+	/// Take the next `len` bytes, or fail if there are not enough.
+	pub fn take(&mut self, len: usize) -> Result<&'a [u8], TagError> {
+		match self.content.get(self.ix..self.ix.saturating_add(len)) {
+			Some(x) => {
+				self.ix += len;
+				Ok(x)
+			}
+			None => Err(TagError::UnexpectedEnd {
+				frame: None,
+				offset: self.ix,
+			}),
+		}
+	}
+
+	pub fn array<const N: usize>(&mut self) -> Result<[u8; N], TagError> {
+		let mut ret = [0; N];
+		ret.copy_from_slice(self.take(N)?);
+		Ok(ret)
+	}
+
+	pub fn byte(&mut self) -> Result<u8, TagError> {
+		Ok(self.array::<1>()?[0])
+	}
+
+	pub fn u32_be(&mut self) -> Result<u32, TagError> {
+		Ok(u32::from_be_bytes(self.array()?))
+	}
+
+	/// A cursor over the next `len` bytes, which are skipped by this one. Offsets stay relative to the same start.
+	pub fn limit(&mut self, len: usize) -> Result<Cursor<'a>, TagError> {
+		let start = self.ix;
+		self.take(len)?;
+		Ok(Cursor {
+			content: &self.content[..self.ix],
+			ix: start,
+		})
+	}
+
+	/// Take everything up to the end.
+	pub fn rest(&mut self) -> &'a [u8] {
+		let ret = &self.content[self.ix..];
+		self.ix = self.content.len();
+		ret
+	}
+
+	/// Read a string in the given ID3v2 text encoding, up to and including its terminator. The terminator is optional
+	/// at the end of the data.
+	pub fn string(&mut self, encoding: u8) -> Result<String, TagError> {
+		let start = self.ix;
+		let (s, len) = read_to_null(&self.content[start..], encoding).map_err(|e| e.shifted(start))?;
+		self.ix += len;
+		Ok(s)
+	}
+
+	/// Read the rest of the data as one string, which isn't terminated.
+	pub fn text(&mut self, encoding: u8) -> Result<String, TagError> {
+		let start = self.ix;
+		read_as_utf8(self.rest(), encoding).map_err(|e| e.shifted(start))
+	}
+
+	/// Read terminated strings up to the end. The terminator after the last string is optional.
+	pub fn values(&mut self, encoding: u8) -> Result<Vec<String>, TagError> {
+		let mut values = Vec::new();
+		while !self.is_empty() {
+			values.push(self.string(encoding)?);
+		}
+		Ok(values)
+	}
+}
+
+/// Read a string from the start of `content`. Returns the string and the length read including the terminator.
+fn read_to_null(content: &[u8], encoding: u8) -> Result<(String, usize), TagError> {
+	let (end, next) = match encoding {
+		0 | 3 => match content.iter().position(|b| *b == 0) {
+			Some(i) => (i, i + 1),
+			None => (content.len(), content.len()),
+		},
+		1 | 2 => match content.chunks_exact(2).position(|a| a == [0, 0]) {
+			Some(i) => (2 * i, 2 * i + 2),
+			None => (content.len(), content.len()),
+		},
+		_ => {
+			return Err(TagError::InvalidEncoding {
+				frame: None,
+				offset: 0,
+				encoding,
+			})
+		}
+	};
+	Ok((read_as_utf8(&content[..end], encoding)?, next))
+}
+
+fn read_as_utf8(content: &[u8], encoding: u8) -> Result<String, TagError> {
+	match encoding {
+		// Every byte is the code point of the same value
+		0 => Ok(content.iter().map(|b| *b as char).collect()),
+		3 => match std::str::from_utf8(content) {
+			Ok(s) => Ok(s.to_string()),
+			Err(e) => Err(TagError::InvalidText {
+				frame: None,
+				offset: e.valid_up_to(),
+			}),
+		},
+		1 => {
+			if content.is_empty() {
+				return Ok(String::new());
+			}
+			let mut cursor = Cursor::new(content);
+			match cursor.array()? {
+				[0xFF, 0xFE] => read_utf16(cursor.rest(), u16::from_le_bytes).map_err(|e| e.shifted(2)),
+				[0xFE, 0xFF] => read_utf16(cursor.rest(), u16::from_be_bytes).map_err(|e| e.shifted(2)),
+				found => Err(TagError::MissingBom {
+					frame: None,
+					offset: 0,
+					found,
+				}),
+			}
+		}
+		2 => read_utf16(content, u16::from_be_bytes),
+		_ => Err(TagError::InvalidEncoding {
+			frame: None,
+			offset: 0,
+			encoding,
+		}),
+	}
+}
+
+/// A trailing odd byte is ignored.
+fn read_utf16(content: &[u8], from_bytes: fn([u8; 2]) -> u16) -> Result<String, TagError> {
+	let uv: Vec<u16> = content.chunks_exact(2).map(|a| from_bytes([a[0], a[1]])).collect();
+	String::from_utf16(&uv).map_err(|_| TagError::InvalidText { frame: None, offset: 0 })
+}
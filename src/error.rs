@@ -0,0 +1,152 @@
+use std::fmt;
+
+/// Errors produced while reading a tag.
+///
+/// Frame-level errors carry the ID of the frame being decoded (when known) and the byte offset at which the problem
+/// was found. Once the frame is known the offset is relative to the start of the tag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TagError {
+	/// The data does not begin with an "ID3" tag header
+	MissingHeader,
+	/// A frame or field extends past the end of the available data
+	UnexpectedEnd { frame: Option<[u8; 4]>, offset: usize },
+	/// A frame has flags set which are not supported
+	UnsupportedFlags {
+		frame: Option<[u8; 4]>,
+		offset: usize,
+		flags: [u8; 2],
+	},
+	/// A frame ID containing characters other than A-Z and 0-9
+	InvalidFrameId { frame: Option<[u8; 4]>, offset: usize },
+	/// The text encoding byte is not one of the four defined encodings
+	InvalidEncoding {
+		frame: Option<[u8; 4]>,
+		offset: usize,
+		encoding: u8,
+	},
+	/// UTF-16 text that does not start with a byte order mark
+	MissingBom {
+		frame: Option<[u8; 4]>,
+		offset: usize,
+		found: [u8; 2],
+	},
+	/// Text which is not valid in its declared encoding
+	InvalidText { frame: Option<[u8; 4]>, offset: usize },
+	/// A compressed frame which could not be decompressed
+	InvalidCompressedData { frame: Option<[u8; 4]>, offset: usize },
+	/// The extended header is malformed
+	InvalidExtendedHeader { offset: usize },
+	/// The CRC-32 in the extended header does not match the tag data
+	CrcMismatch { expected: u32, calculated: u32 },
+	/// Reading from the underlying file failed
+	Io(String),
+}
+
+impl TagError {
+	fn location_mut(&mut self) -> Option<(&mut Option<[u8; 4]>, &mut usize)> {
+		match self {
+			TagError::MissingHeader
+			| TagError::InvalidExtendedHeader { .. }
+			| TagError::CrcMismatch { .. }
+			| TagError::Io(_) => None,
+			TagError::UnexpectedEnd { frame, offset }
+			| TagError::UnsupportedFlags { frame, offset, .. }
+			| TagError::InvalidFrameId { frame, offset }
+			| TagError::InvalidEncoding { frame, offset, .. }
+			| TagError::MissingBom { frame, offset, .. }
+			| TagError::InvalidText { frame, offset }
+			| TagError::InvalidCompressedData { frame, offset } => Some((frame, offset)),
+		}
+	}
+
+	/// Attach a frame ID to an error raised while decoding that frame's content. The offset is shifted from being
+	/// relative to the frame content to being relative to the tag, starting at `base`.
+	pub fn in_frame(mut self, id: [u8; 4], base: usize) -> Self {
+		if let Some((frame, offset)) = self.location_mut() {
+			if frame.is_none() {
+				*frame = Some(id);
+				*offset += base;
+			}
+		}
+		self
+	}
+
+	/// Shift the offset of an error raised on a sub-slice so it is relative to the enclosing slice.
+	pub(crate) fn shifted(mut self, by: usize) -> Self {
+		if let Some((None, offset)) = self.location_mut() {
+			*offset += by;
+		}
+		self
+	}
+}
+
+fn frame_name(frame: &Option<[u8; 4]>) -> String {
+	match frame {
+		Some(id) => String::from(" in frame ") + &String::from_utf8_lossy(id),
+		None => String::new(),
+	}
+}
+
+impl fmt::Display for TagError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			TagError::MissingHeader => write!(f, "No ID3 header found"),
+			TagError::UnexpectedEnd { frame, offset } => {
+				write!(f, "Unexpected end of data{} at offset {}", frame_name(frame), offset)
+			}
+			TagError::UnsupportedFlags { frame, offset, flags } => write!(
+				f,
+				"Unsupported flags {:#04X} {:#04X}{} at offset {}",
+				flags[0],
+				flags[1],
+				frame_name(frame),
+				offset
+			),
+			TagError::InvalidFrameId { frame, offset } => {
+				write!(f, "Invalid frame ID{} at offset {}", frame_name(frame), offset)
+			}
+			TagError::InvalidEncoding {
+				frame,
+				offset,
+				encoding,
+			} => write!(
+				f,
+				"Invalid text encoding {}{} at offset {}",
+				encoding,
+				frame_name(frame),
+				offset
+			),
+			TagError::MissingBom { frame, offset, found } => write!(
+				f,
+				"Expected FF FE or FE FF{} at offset {}, got {:02X} {:02X}",
+				frame_name(frame),
+				offset,
+				found[0],
+				found[1]
+			),
+			TagError::InvalidText { frame, offset } => {
+				write!(f, "Invalid text{} at offset {}", frame_name(frame), offset)
+			}
+			TagError::InvalidCompressedData { frame, offset } => {
+				write!(f, "Invalid compressed data{} at offset {}", frame_name(frame), offset)
+			}
+			TagError::InvalidExtendedHeader { offset } => {
+				write!(f, "Invalid extended header at offset {}", offset)
+			}
+			TagError::CrcMismatch { expected, calculated } => write!(
+				f,
+				"CRC mismatch: extended header has {:#010X}, tag data has {:#010X}",
+				expected, calculated
+			),
+			TagError::Io(e) => write!(f, "Read error: {}", e),
+		}
+	}
+}
+
+impl std::error::Error for TagError {}
+
+impl From<std::io::Error> for TagError {
+	fn from(e: std::io::Error) -> Self {
+		TagError::Io(e.to_string())
+	}
+}
@@ -1,11 +1,17 @@
 // use std::vec::Vec;
+use std::io::Write;
 
 pub struct ID3v240Tag {
 	pub header: ID3Header,
 	pub extended_header: Option<ID3ExtendedHeader>,
 	pub frames: Vec<ID3Frame>,
+	/// Frames read from an older version of ID3v2 which could not be upgraded to ID3v2.4. They are not written.
+	/// ID3v2.2 frames keep their 3 character ID followed by a space.
+	pub unconverted: Vec<ID3Frame>,
 	pub padding: u32,
 	pub has_footer: bool,
+	/// The encoding to write text in where the version and the text allow it
+	pub text_encoding: TextEncoding,
 }
 
 pub struct ID3Header {
@@ -16,22 +22,81 @@ pub struct ID3Header {
 }
 
 impl ID3v240Tag {
-	pub fn bytes(&self) -> Vec<u8> {
-		let mut ret = Vec::<u8>::new();
-		ret.extend(self.header.bytes());
+	/// The total size of the tag as read: header, extended header, frames, padding and footer.
+	pub fn size(&self) -> usize {
+		10 + self.header.size as usize + if self.has_footer { 10 } else { 0 }
+	}
 
-		if let Some(ex) = &self.extended_header {
-			ret.extend(ex.bytes());
+	/// Set the padding so that the tag takes exactly `size` bytes, such as the space of the tag it replaces. Returns
+	/// false and leaves the tag unchanged if the frames don't fit.
+	pub fn pad_to(&mut self, size: usize) -> bool {
+		let (padding, has_footer) = (self.padding, self.has_footer);
+		self.padding = 0;
+		self.has_footer = false;
+		let unpadded = self.bytes().len();
+		if unpadded > size || size - unpadded > u32::MAX as usize {
+			self.padding = padding;
+			self.has_footer = has_footer;
+			return false;
 		}
+		self.padding = (size - unpadded) as u32;
+		true
+	}
+
+	/// Serialise the tag. `header.version_major` selects the output format: 3 writes ID3v2.3 (frames which only exist
+	/// in ID3v2.4 are converted or dropped, and no footer is written), anything else writes ID3v2.4. The size in the
+	/// header is calculated from the content.
+	///
+	/// A tag with padding has no footer, as ID3v2.4 does not allow both.
+	pub fn bytes(&self) -> Vec<u8> {
+		let version = if self.header.version_major == 3 { 3 } else { 4 };
+		let text_encoding = self.text_encoding;
+		let has_footer = self.has_footer && version == 4 && self.padding == 0;
+		let unsync = self.header.unsynchronisation();
 
-		for frame in &self.frames {
-			ret.extend(frame.bytes());
+		let mut body = Vec::<u8>::new();
+		if version == 3 {
+			for frame in downgrade_to_v23(&self.frames) {
+				body.extend(frame.bytes(version, text_encoding));
+			}
+			// The CRC covers the frames before unsynchronisation
+			if let Some(ex) = &self.extended_header {
+				body = [ex.bytes(version, self.padding, &body), body].concat();
+			}
+			if unsync {
+				body = unsynchronise(&body);
+			}
+			body.extend(std::iter::repeat_n(0, self.padding as usize));
+		} else {
+			for frame in &self.frames {
+				body.extend(frame.frame_bytes(version, text_encoding, unsync));
+			}
+			body.extend(std::iter::repeat_n(0, self.padding as usize));
+			// The CRC covers the frames and padding
+			if let Some(ex) = &self.extended_header {
+				body = [ex.bytes(version, self.padding, &body), body].concat();
+			}
 		}
 
-		ret.extend(std::iter::repeat(0).take(self.padding as usize));
+		let mut flags = self.header.flags & !0b0101_0000;
+		if has_footer {
+			flags |= 0b0001_0000;
+		}
+		if self.extended_header.is_some() {
+			flags |= 0b0100_0000;
+		}
+		let header = ID3Header {
+			version_major: version,
+			version_minor: 0,
+			flags,
+			size: body.len() as u32,
+		};
 
-		if self.has_footer {
-			ret.extend(self.header.footer_bytes());
+		let mut ret = Vec::<u8>::with_capacity(10 + body.len() + 10);
+		ret.extend(header.bytes());
+		ret.extend(body);
+		if has_footer {
+			ret.extend(header.footer_bytes());
 		}
 
 		ret
@@ -40,16 +105,16 @@ impl ID3v240Tag {
 
 impl ID3Header {
 	pub fn unsynchronisation(&self) -> bool {
-		self.flags & 0b0100_0000 != 0
+		self.flags & 0b1000_0000 != 0
 	}
 	pub fn extended_header(&self) -> bool {
-		self.flags & 0b0010_0000 != 0
+		self.flags & 0b0100_0000 != 0
 	}
 	pub fn experimental_indicator(&self) -> bool {
-		self.flags & 0b0001_0000 != 0
+		self.flags & 0b0010_0000 != 0
 	}
 	pub fn footer_present(&self) -> bool {
-		self.flags & 0b0000_1000 != 0
+		self.flags & 0b0001_0000 != 0
 	}
 	pub fn bytes(&self) -> Vec<u8> {
 		let mut ret = Vec::<u8>::new();
@@ -73,52 +138,237 @@ impl ID3Header {
 	}
 }
 
+#[derive(Clone, Default)]
 pub struct ID3ExtendedHeader {
-	pub size: u32,
-	pub fields: Vec<ID3ExtendedFlag>,
+	/// ID3v2.3 only. The size of the padding after the frames.
+	pub padding_size: u32,
+	/// ID3v2.4 only. This tag is an update of an earlier tag in the file.
+	pub tag_is_update: bool,
+	/// CRC-32 of the tag data. When writing, a value of Some is replaced with a freshly calculated CRC.
+	pub crc: Option<u32>,
+	/// ID3v2.4 only
+	pub restrictions: Option<ID3TagRestrictions>,
 }
 
 impl ID3ExtendedHeader {
-	pub fn bytes(&self) -> Vec<u8> {
+	/// Serialise the extended header for ID3v2.`version`. `crc_data` is the data the CRC is calculated over: the
+	/// frames in ID3v2.3, and the frames and padding in ID3v2.4.
+	pub fn bytes(&self, version: u8, padding: u32, crc_data: &[u8]) -> Vec<u8> {
+		let crc = self.crc.map(|_| crc32(crc_data));
 		let mut ret = Vec::<u8>::new();
-		ret.extend(&synchsafe_bytes(self.size));
-		for field in &self.fields {
-			ret.extend(field.bytes());
+		if version == 3 {
+			let size: u32 = if crc.is_some() { 10 } else { 6 };
+			ret.extend(size.to_be_bytes());
+			ret.push(if crc.is_some() { 0b1000_0000 } else { 0 });
+			ret.push(0);
+			ret.extend(padding.to_be_bytes());
+			if let Some(crc) = crc {
+				ret.extend(crc.to_be_bytes());
+			}
+		} else {
+			let mut flags = 0;
+			let mut flag_data = Vec::<u8>::new();
+			if self.tag_is_update {
+				flags |= 0b0100_0000;
+				flag_data.push(0);
+			}
+			if let Some(crc) = crc {
+				flags |= 0b0010_0000;
+				flag_data.push(5);
+				// 35 bit synchsafe integer
+				flag_data.push((crc >> 28) as u8);
+				flag_data.extend(synchsafe_bytes(crc));
+			}
+			if let Some(restrictions) = &self.restrictions {
+				flags |= 0b0001_0000;
+				flag_data.push(1);
+				flag_data.push(restrictions.byte());
+			}
+			ret.extend(synchsafe_bytes(6 + flag_data.len() as u32));
+			ret.push(1);
+			ret.push(flags);
+			ret.extend(flag_data);
 		}
 		ret
 	}
 }
 
-pub struct ID3ExtendedFlag {
-	// n: u8,
-	pub flags: Vec<u8>,
+/// ID3v2.4 tag restrictions. Each size field is the 2 bit value from the spec.
+#[derive(Clone, Copy, Default)]
+pub struct ID3TagRestrictions {
+	/// 0: 128 frames and 1 MB, 1: 64 frames and 128 KB, 2: 32 frames and 40 KB, 3: 32 frames and 4 KB
+	pub tag_size: u8,
+	/// Only ISO-8859-1 and UTF-8 text
+	pub text_encoding: bool,
+	/// 0: No restriction, 1: 1024 characters, 2: 128 characters, 3: 30 characters
+	pub text_fields_size: u8,
+	/// Only PNG and JPEG images
+	pub image_encoding: bool,
+	/// 0: No restriction, 1: 256x256 or smaller, 2: 64x64 or smaller, 3: Exactly 64x64
+	pub image_size: u8,
 }
 
-impl ID3ExtendedFlag {
-	pub fn bytes(&self) -> Vec<u8> {
-		let mut ret = Vec::<u8>::new();
-		ret.insert(0, self.flags.len() as u8);
-		ret
+impl ID3TagRestrictions {
+	pub fn from_byte(b: u8) -> Self {
+		ID3TagRestrictions {
+			tag_size: b >> 6,
+			text_encoding: b & 0b0010_0000 != 0,
+			text_fields_size: (b >> 3) & 0b11,
+			image_encoding: b & 0b0000_0100 != 0,
+			image_size: b & 0b11,
+		}
+	}
+	pub fn byte(&self) -> u8 {
+		((self.tag_size & 0b11) << 6)
+			| ((self.text_encoding as u8) << 5)
+			| ((self.text_fields_size & 0b11) << 3)
+			| ((self.image_encoding as u8) << 2)
+			| (self.image_size & 0b11)
 	}
 }
 
 #[derive(Clone)]
 pub struct ID3Frame {
 	pub id: [u8; 4],
-	pub flags: [u8; 2],
+	pub flags: ID3FrameFlags,
 	pub data: ID3FrameType,
 }
 
+/// Frame status and format flags. The values that accompany some flags (group ID, encryption method and data length)
+/// are held with the flag.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct ID3FrameFlags {
+	/// Discard the frame if it is unknown and the tag is altered
+	pub tag_alter_preservation: bool,
+	/// Discard the frame if it is unknown and the audio is altered
+	pub file_alter_preservation: bool,
+	pub read_only: bool,
+	/// The group this frame belongs to
+	pub grouping_identity: Option<u8>,
+	/// Compress the content with zlib when written
+	pub compression: bool,
+	/// The encryption method. Encrypted content can't be parsed so is kept as a raw frame.
+	pub encryption: Option<u8>,
+	/// ID3v2.4 only. Unsynchronise the frame when written.
+	pub unsynchronisation: bool,
+	/// The size of the content without compression or unsynchronisation. This is always written for compressed
+	/// frames, and is recalculated unless the frame is encrypted.
+	pub data_length_indicator: Option<u32>,
+}
+
+impl ID3FrameFlags {
+	pub fn is_empty(&self) -> bool {
+		*self == ID3FrameFlags::default()
+	}
+	pub fn display(&self) -> String {
+		let mut ret = Vec::<String>::new();
+		if self.tag_alter_preservation {
+			ret.push(String::from("discard on tag alteration"));
+		}
+		if self.file_alter_preservation {
+			ret.push(String::from("discard on file alteration"));
+		}
+		if self.read_only {
+			ret.push(String::from("read only"));
+		}
+		if let Some(group) = self.grouping_identity {
+			ret.push(format!("group {:#04X}", group));
+		}
+		if self.compression {
+			ret.push(String::from("compressed"));
+		}
+		if let Some(method) = self.encryption {
+			ret.push(format!("encryption method {:#04X}", method));
+		}
+		if self.unsynchronisation {
+			ret.push(String::from("unsynchronised"));
+		}
+		if let Some(len) = self.data_length_indicator {
+			ret.push(format!("data length {}", len));
+		}
+		ret.join(", ")
+	}
+}
+
 impl ID3Frame {
-	pub fn bytes(&self) -> Vec<u8> {
-		let mut ret = Vec::<u8>::new();
+	/// Serialise the frame for ID3v2.`version`. ID3v2.3 frame sizes are plain big-endian integers, ID3v2.4 sizes are
+	/// synchsafe. The content is compressed, grouped and (in ID3v2.4) unsynchronised according to the frame flags.
+	pub fn bytes(&self, version: u8, text_encoding: TextEncoding) -> Vec<u8> {
+		self.frame_bytes(version, text_encoding, false)
+	}
+	/// As `bytes`, with `unsync` forcing ID3v2.4 unsynchronisation regardless of the frame's own flag.
+	fn frame_bytes(&self, version: u8, text_encoding: TextEncoding, unsync: bool) -> Vec<u8> {
+		let flags = &self.flags;
+		let mut data_bytes = self.data.bytes(version, text_encoding);
+		// Encrypted frames are kept exactly as they were read
+		let encrypted = flags.encryption.is_some();
+		let data_length = if encrypted {
+			flags.data_length_indicator.unwrap_or(0)
+		} else {
+			data_bytes.len() as u32
+		};
+		if flags.compression && !encrypted {
+			data_bytes = compress(&data_bytes);
+		}
+
+		let mut status = 0u8;
+		let mut format = 0u8;
+		let mut prefix = Vec::<u8>::new();
+		if version == 3 {
+			status |= (flags.tag_alter_preservation as u8) << 7;
+			status |= (flags.file_alter_preservation as u8) << 6;
+			status |= (flags.read_only as u8) << 5;
+			if flags.compression {
+				format |= 0b1000_0000;
+				prefix.extend(data_length.to_be_bytes());
+			}
+			if let Some(method) = flags.encryption {
+				format |= 0b0100_0000;
+				prefix.push(method);
+			}
+			if let Some(group) = flags.grouping_identity {
+				format |= 0b0010_0000;
+				prefix.push(group);
+			}
+		} else {
+			status |= (flags.tag_alter_preservation as u8) << 6;
+			status |= (flags.file_alter_preservation as u8) << 5;
+			status |= (flags.read_only as u8) << 4;
+			if let Some(group) = flags.grouping_identity {
+				format |= 0b0100_0000;
+				prefix.push(group);
+			}
+			if flags.compression {
+				format |= 0b0000_1000;
+			}
+			if let Some(method) = flags.encryption {
+				format |= 0b0000_0100;
+				prefix.push(method);
+			}
+			// Compressed frames must have a data length indicator
+			if flags.data_length_indicator.is_some() || flags.compression {
+				format |= 0b0000_0001;
+				prefix.extend(synchsafe_bytes(data_length));
+			}
+		}
+
+		let mut data_bytes = [prefix, data_bytes].concat();
+		// Unsynchronisation is a tag level setting in ID3v2.3
+		if version != 3 && (unsync || flags.unsynchronisation) {
+			format |= 0b0000_0010;
+			data_bytes = unsynchronise(&data_bytes);
+		}
+
+		let mut ret = Vec::<u8>::with_capacity(10 + data_bytes.len());
 		ret.extend(&self.id);
-		// ret.extend(&synchsafe_bytes(self.size));
-		// ret.extend(&self.size.to_be_bytes());
-		let data_bytes = &self.data.bytes();
-		ret.extend(&synchsafe_bytes(data_bytes.len() as u32));
-		ret.extend(&self.flags);
-		ret.extend(data_bytes);
+		if version == 3 {
+			ret.extend(&(data_bytes.len() as u32).to_be_bytes());
+		} else {
+			ret.extend(&synchsafe_bytes(data_bytes.len() as u32));
+		}
+		ret.push(status);
+		ret.push(format);
+		ret.extend(&data_bytes);
 		ret
 	}
 	pub fn display(&self) -> String {
@@ -131,53 +381,548 @@ pub enum ID3FrameType {
 	Text(ID3TextFrame),
 	Picture(ID3PictureFrame),
 	Comment(ID3CommentFrame),
+	/// TXXX
+	UserText(ID3UserTextFrame),
+	/// W*** other than WXXX
+	Url(ID3UrlFrame),
+	/// WXXX
+	UserUrl(ID3UserUrlFrame),
+	/// UFID
+	UniqueFileId(ID3UniqueFileIdFrame),
+	/// PRIV
+	Private(ID3PrivateFrame),
+	/// POPM
+	Popularimeter(ID3PopularimeterFrame),
+	/// PCNT
+	PlayCounter(ID3PlayCounterFrame),
+	/// SYLT
+	SyncLyrics(ID3SyncLyricsFrame),
+	/// CHAP
+	Chapter(ID3ChapterFrame),
+	/// CTOC
+	TableOfContents(ID3TableOfContentsFrame),
+	/// GEOB
+	GeneralObject(ID3GeneralObjectFrame),
+	/// RVA2
+	RelativeVolume(ID3RelativeVolumeFrame),
+	/// A frame whose content is not parsed. The payload is kept verbatim.
+	Raw(Vec<u8>),
 }
 
 impl ID3FrameType {
 	#[allow(clippy::len_without_is_empty)]
 	pub fn len(&self) -> usize {
 		match self {
-			ID3FrameType::Text(f) => 1 + f.data.len(),
+			ID3FrameType::Text(f) => 1 + f.values.iter().fold(0, |acc, v| acc + v.len() + 1),
 			ID3FrameType::Picture(f) => 1 + f.mime.len() + 1 + 1 + f.description.len() + 1 + f.data.len(),
 			ID3FrameType::Comment(f) => 1 + 3 + f.content_desc.len() + 1 + f.text.len(),
+			ID3FrameType::UserText(f) => 1 + f.description.len() + f.values.iter().fold(0, |acc, v| acc + 1 + v.len()),
+			ID3FrameType::Url(f) => f.url.len(),
+			ID3FrameType::UserUrl(f) => 1 + f.description.len() + 1 + f.url.len(),
+			ID3FrameType::UniqueFileId(f) => f.owner.len() + 1 + f.identifier.len(),
+			ID3FrameType::Private(f) => f.owner.len() + 1 + f.data.len(),
+			ID3FrameType::Popularimeter(f) => f.email.len() + 1 + 1 + f.counter.map_or(0, |c| counter_bytes(c).len()),
+			ID3FrameType::PlayCounter(f) => counter_bytes(f.counter).len(),
+			ID3FrameType::SyncLyrics(f) => {
+				1 + 3 + 1 + 1 + f.description.len() + 1 + f.lyrics.iter().fold(0, |acc, (t, _)| acc + t.len() + 1 + 4)
+			}
+			ID3FrameType::Chapter(f) => f.element_id.len() + 1 + 16 + frames_len(&f.frames),
+			ID3FrameType::TableOfContents(f) => {
+				f.element_id.len()
+					+ 1 + 2 + f.written_children().iter().fold(0, |acc, c| acc + c.len() + 1)
+					+ frames_len(&f.frames)
+			}
+			ID3FrameType::GeneralObject(f) => {
+				1 + f.mime.len() + 1 + f.filename.len() + 1 + f.description.len() + 1 + f.data.len()
+			}
+			ID3FrameType::RelativeVolume(f) => {
+				f.identification.len() + 1 + f.channels.iter().fold(0, |acc, c| acc + 1 + 2 + 1 + c.peak.len())
+			}
+			ID3FrameType::Raw(data) => data.len(),
 		}
 	}
-	pub fn bytes(&self) -> Vec<u8> {
+	pub fn bytes(&self, version: u8, text_encoding: TextEncoding) -> Vec<u8> {
 		match self {
-			ID3FrameType::Text(f) => f.bytes(),
-			ID3FrameType::Picture(f) => f.bytes(),
-			ID3FrameType::Comment(f) => f.bytes(),
+			ID3FrameType::Text(f) => f.bytes(version, text_encoding),
+			ID3FrameType::Picture(f) => f.bytes(version, text_encoding),
+			ID3FrameType::Comment(f) => f.bytes(version, text_encoding),
+			ID3FrameType::UserText(f) => f.bytes(version, text_encoding),
+			ID3FrameType::Url(f) => f.bytes(),
+			ID3FrameType::UserUrl(f) => f.bytes(version, text_encoding),
+			ID3FrameType::UniqueFileId(f) => f.bytes(),
+			ID3FrameType::Private(f) => f.bytes(),
+			ID3FrameType::Popularimeter(f) => f.bytes(),
+			ID3FrameType::PlayCounter(f) => f.bytes(),
+			ID3FrameType::SyncLyrics(f) => f.bytes(version, text_encoding),
+			ID3FrameType::Chapter(f) => f.bytes(version, text_encoding),
+			ID3FrameType::TableOfContents(f) => f.bytes(version, text_encoding),
+			ID3FrameType::GeneralObject(f) => f.bytes(version, text_encoding),
+			ID3FrameType::RelativeVolume(f) => f.bytes(),
+			ID3FrameType::Raw(data) => data.clone(),
 		}
 	}
 	pub fn display(&self) -> String {
 		match self {
-			ID3FrameType::Text(f) => f.data.clone(),
+			ID3FrameType::Text(f) => f.values.join(";"),
 			ID3FrameType::Picture(_) => String::from("Picture"),
 			ID3FrameType::Comment(f) => {
 				let lang = String::from_utf8_lossy(&f.language);
 				f.content_desc.clone() + ":" + &lang + ":" + &f.text
 			}
+			ID3FrameType::UserText(f) => f.description.clone() + "=" + &f.values.join(";"),
+			ID3FrameType::Url(f) => f.url.clone(),
+			ID3FrameType::UserUrl(f) => f.description.clone() + "=" + &f.url,
+			ID3FrameType::UniqueFileId(f) => f.owner.clone() + "=" + &String::from_utf8_lossy(&f.identifier),
+			ID3FrameType::Private(f) => format!("{}={} bytes", f.owner, f.data.len()),
+			ID3FrameType::Popularimeter(f) => match f.counter {
+				Some(counter) => format!("{}={}/255, {} plays", f.email, f.rating, counter),
+				None => format!("{}={}/255", f.email, f.rating),
+			},
+			ID3FrameType::PlayCounter(f) => format!("{} plays", f.counter),
+			ID3FrameType::SyncLyrics(f) => {
+				let lang = String::from_utf8_lossy(&f.language);
+				format!("{}:{}:{} lines", f.description, lang, f.lyrics.len())
+			}
+			ID3FrameType::Chapter(f) => format!("{}:{}-{} ms:{}", f.element_id, f.start_time, f.end_time, f.title()),
+			ID3FrameType::TableOfContents(f) => f.element_id.clone() + ":" + &f.children.join(";"),
+			ID3FrameType::GeneralObject(f) => {
+				format!("{}:{}:{}:{} bytes", f.description, f.mime, f.filename, f.data.len())
+			}
+			ID3FrameType::RelativeVolume(f) => {
+				let channels: Vec<String> = f
+					.channels
+					.iter()
+					.map(|c| format!("{}={:+.2} dB", c.channel_type, c.gain_db()))
+					.collect();
+				f.identification.clone() + ":" + &channels.join(";")
+			}
+			ID3FrameType::Raw(data) => format!("{} bytes", data.len()),
+		}
+	}
+	/// The owner identifier of frames which may be repeated once per owner.
+	pub fn owner(&self) -> Option<&str> {
+		match self {
+			ID3FrameType::UniqueFileId(f) => Some(&f.owner),
+			ID3FrameType::Private(f) => Some(&f.owner),
+			ID3FrameType::Popularimeter(f) => Some(&f.email),
+			ID3FrameType::GeneralObject(f) => Some(&f.description),
+			ID3FrameType::RelativeVolume(f) => Some(&f.identification),
+			_ => None,
 		}
 	}
 }
 
+/// Find the frame with the given ID that belongs to `owner`, such as the UFID written by MusicBrainz
+/// ("http://musicbrainz.org").
+pub fn find_by_owner<'a>(frames: &'a [ID3Frame], id: &[u8; 4], owner: &str) -> Option<&'a ID3Frame> {
+	frames.iter().find(|f| f.id == *id && f.data.owner() == Some(owner))
+}
+
 #[derive(Clone)]
 pub struct ID3TextFrame {
 	// lang: [u8; 3],
-	pub data: String,
+	/// ID3v2.4 allows several values in one frame. They are written separated by the terminator.
+	pub values: Vec<String>,
 	pub encoding: u8,
 }
 
 impl ID3TextFrame {
+	pub fn bytes(&self, version: u8, text_encoding: TextEncoding) -> Vec<u8> {
+		let strings: Vec<&str> = self.values.iter().map(String::as_str).collect();
+		let encoding = text_encoding.choose(version, &strings);
+		let mut ret = Vec::<u8>::with_capacity(1 + self.values.iter().fold(0, |acc, v| acc + v.len() + 1));
+		ret.push(encoding);
+		for (i, value) in self.values.iter().enumerate() {
+			if i > 0 {
+				ret.extend(terminator(encoding));
+			}
+			ret.extend(encode_text(value, encoding));
+		}
+		ret
+	}
+}
+
+/// User defined text information frame (TXXX)
+#[derive(Clone)]
+pub struct ID3UserTextFrame {
+	pub description: String,
+	pub values: Vec<String>,
+	pub encoding: u8,
+}
+
+impl ID3UserTextFrame {
+	pub fn bytes(&self, version: u8, text_encoding: TextEncoding) -> Vec<u8> {
+		let mut strings = vec![self.description.as_str()];
+		strings.extend(self.values.iter().map(String::as_str));
+		let encoding = text_encoding.choose(version, &strings);
+		let mut ret = Vec::<u8>::new();
+		ret.push(encoding);
+		ret.extend(encode_text(&self.description, encoding));
+		// Multiple values are separated by the terminator. Each UTF-16 string has its own byte order mark.
+		for value in &self.values {
+			ret.extend(terminator(encoding));
+			ret.extend(encode_text(value, encoding));
+		}
+		ret
+	}
+}
+
+/// URL link frame. The URL is always ISO-8859-1 and is not terminated.
+#[derive(Clone)]
+pub struct ID3UrlFrame {
+	pub url: String,
+}
+
+impl ID3UrlFrame {
 	pub fn bytes(&self) -> Vec<u8> {
-		let encoding = if self.data.chars().all(|x| x.is_ascii()) { 0 } else { 3 };
-		let mut ret = Vec::<u8>::with_capacity(1 + self.data.len());
+		encode_text(&self.url, 0)
+	}
+}
+
+/// User defined URL link frame (WXXX). Only the description follows the encoding byte; the URL is ISO-8859-1.
+#[derive(Clone)]
+pub struct ID3UserUrlFrame {
+	pub description: String,
+	pub url: String,
+	pub encoding: u8,
+}
+
+impl ID3UserUrlFrame {
+	pub fn bytes(&self, version: u8, text_encoding: TextEncoding) -> Vec<u8> {
+		let encoding = text_encoding.choose(version, &[&self.description]);
+		let mut ret = Vec::<u8>::with_capacity(1 + self.description.len() + 1 + self.url.len());
+		ret.push(encoding);
+		ret.extend(encode_text(&self.description, encoding));
+		ret.extend(terminator(encoding));
+		ret.extend(encode_text(&self.url, 0));
+		ret
+	}
+}
+
+/// Unique file identifier frame (UFID). The owner is an ISO-8859-1 string, usually a URL, and the identifier is up to
+/// 64 bytes of binary data.
+#[derive(Clone)]
+pub struct ID3UniqueFileIdFrame {
+	pub owner: String,
+	pub identifier: Vec<u8>,
+}
+
+impl ID3UniqueFileIdFrame {
+	pub fn bytes(&self) -> Vec<u8> {
+		let mut ret = Vec::<u8>::with_capacity(self.owner.len() + 1 + self.identifier.len());
+		ret.extend(encode_text(&self.owner, 0));
+		ret.push(0);
+		ret.extend(&self.identifier);
+		ret
+	}
+}
+
+/// Private frame (PRIV). The owner is an ISO-8859-1 string and the data is opaque.
+#[derive(Clone)]
+pub struct ID3PrivateFrame {
+	pub owner: String,
+	pub data: Vec<u8>,
+}
+
+impl ID3PrivateFrame {
+	pub fn bytes(&self) -> Vec<u8> {
+		let mut ret = Vec::<u8>::with_capacity(self.owner.len() + 1 + self.data.len());
+		ret.extend(encode_text(&self.owner, 0));
+		ret.push(0);
+		ret.extend(&self.data);
+		ret
+	}
+}
+
+/// Popularimeter frame (POPM). The rating runs from 1 (worst) to 255 (best), with 0 meaning unknown. The play
+/// counter may be omitted.
+#[derive(Clone)]
+pub struct ID3PopularimeterFrame {
+	pub email: String,
+	pub rating: u8,
+	pub counter: Option<u64>,
+}
+
+impl ID3PopularimeterFrame {
+	pub fn bytes(&self) -> Vec<u8> {
+		let mut ret = Vec::<u8>::with_capacity(self.email.len() + 1 + 1 + 4);
+		ret.extend(encode_text(&self.email, 0));
+		ret.push(0);
+		ret.push(self.rating);
+		if let Some(counter) = self.counter {
+			ret.extend(counter_bytes(counter));
+		}
+		ret
+	}
+}
+
+/// Play counter frame (PCNT)
+#[derive(Clone)]
+pub struct ID3PlayCounterFrame {
+	pub counter: u64,
+}
+
+impl ID3PlayCounterFrame {
+	pub fn bytes(&self) -> Vec<u8> {
+		counter_bytes(self.counter)
+	}
+}
+
+/// Counters are big-endian and at least 32 bits, with a byte added whenever they would overflow.
+fn counter_bytes(counter: u64) -> Vec<u8> {
+	let bytes = counter.to_be_bytes();
+	let start = bytes.iter().take(4).take_while(|b| **b == 0).count();
+	bytes[start..].to_vec()
+}
+
+/// Synchronised lyrics/text frame (SYLT)
+#[derive(Clone)]
+pub struct ID3SyncLyricsFrame {
+	pub language: [u8; 3],
+	/// 1: MPEG frames, 2: milliseconds
+	pub timestamp_format: u8,
+	/// 0: other, 1: lyrics, 2: text transcription, 3: movement/part name, 4: events, 5: chord, 6: trivia, 7: URLs to
+	/// webpages, 8: URLs to images
+	pub content_type: u8,
+	pub description: String,
+	/// Each piece of text along with the time at which it starts
+	pub lyrics: Vec<(String, u32)>,
+	pub encoding: u8,
+}
+
+impl ID3SyncLyricsFrame {
+	pub fn bytes(&self, version: u8, text_encoding: TextEncoding) -> Vec<u8> {
+		let mut strings = vec![self.description.as_str()];
+		strings.extend(self.lyrics.iter().map(|(text, _)| text.as_str()));
+		let encoding = text_encoding.choose(version, &strings);
+		let mut ret = Vec::<u8>::new();
+		ret.push(encoding);
+		ret.extend(&self.language);
+		ret.push(self.timestamp_format);
+		ret.push(self.content_type);
+		ret.extend(encode_text(&self.description, encoding));
+		ret.extend(terminator(encoding));
+		for (text, timestamp) in &self.lyrics {
+			ret.extend(encode_text(text, encoding));
+			ret.extend(terminator(encoding));
+			ret.extend(timestamp.to_be_bytes());
+		}
+		ret
+	}
+}
+
+/// Chapter frame (CHAP) from the ID3v2 Chapter Frame Addendum. The frames embedded in it, usually TIT2 and APIC,
+/// describe the chapter.
+#[derive(Clone)]
+pub struct ID3ChapterFrame {
+	pub element_id: String,
+	/// Milliseconds from the start of the audio
+	pub start_time: u32,
+	pub end_time: u32,
+	/// Byte offsets from the start of the audio, or 0xFFFFFFFF when only the times are used
+	pub start_offset: u32,
+	pub end_offset: u32,
+	pub frames: Vec<ID3Frame>,
+}
+
+impl ID3ChapterFrame {
+	pub fn bytes(&self, version: u8, text_encoding: TextEncoding) -> Vec<u8> {
+		let mut ret = Vec::<u8>::new();
+		ret.extend(encode_text(&self.element_id, 0));
+		ret.push(0);
+		ret.extend(self.start_time.to_be_bytes());
+		ret.extend(self.end_time.to_be_bytes());
+		ret.extend(self.start_offset.to_be_bytes());
+		ret.extend(self.end_offset.to_be_bytes());
+		ret.extend(embedded_frame_bytes(&self.frames, version, text_encoding));
+		ret
+	}
+	/// The text of the embedded TIT2 frame, if there is one.
+	pub fn title(&self) -> String {
+		self
+			.frames
+			.iter()
+			.find_map(|f| match &f.data {
+				ID3FrameType::Text(t) if &f.id == b"TIT2" => Some(t.values.join("/")),
+				_ => None,
+			})
+			.unwrap_or_default()
+	}
+}
+
+/// Table of contents frame (CTOC) from the ID3v2 Chapter Frame Addendum. The children are the element IDs of CHAP
+/// frames or of other CTOC frames.
+#[derive(Clone)]
+pub struct ID3TableOfContentsFrame {
+	pub element_id: String,
+	/// The root of the table of contents tree
+	pub top_level: bool,
+	/// Whether the children should be played in order
+	pub ordered: bool,
+	/// Only the first 255 children are written, as the count is a single byte
+	pub children: Vec<String>,
+	pub frames: Vec<ID3Frame>,
+}
+
+impl ID3TableOfContentsFrame {
+	/// The children which fit in the frame
+	fn written_children(&self) -> &[String] {
+		&self.children[..self.children.len().min(u8::MAX as usize)]
+	}
+	pub fn bytes(&self, version: u8, text_encoding: TextEncoding) -> Vec<u8> {
+		let mut ret = Vec::<u8>::new();
+		ret.extend(encode_text(&self.element_id, 0));
+		ret.push(0);
+		ret.push(if self.top_level { 0b10 } else { 0 } | if self.ordered { 0b01 } else { 0 });
+		let children = self.written_children();
+		ret.push(children.len() as u8);
+		for child in children {
+			ret.extend(encode_text(child, 0));
+			ret.push(0);
+		}
+		ret.extend(embedded_frame_bytes(&self.frames, version, text_encoding));
+		ret
+	}
+}
+
+fn frames_len(frames: &[ID3Frame]) -> usize {
+	frames.iter().fold(0, |acc, f| acc + 10 + f.data.len())
+}
+
+fn embedded_frame_bytes(frames: &[ID3Frame], version: u8, text_encoding: TextEncoding) -> Vec<u8> {
+	if version == 3 {
+		downgrade_to_v23(frames)
+			.iter()
+			.flat_map(|f| f.bytes(version, text_encoding))
+			.collect()
+	} else {
+		frames.iter().flat_map(|f| f.bytes(version, text_encoding)).collect()
+	}
+}
+
+/// General encapsulated object frame (GEOB). The MIME type is always ISO-8859-1.
+#[derive(Clone)]
+pub struct ID3GeneralObjectFrame {
+	pub mime: String,
+	pub filename: String,
+	pub description: String,
+	pub data: Vec<u8>,
+	pub encoding: u8,
+	/// The byte order marks of the filename and description in UTF-16 (encoding 1)
+	pub filename_bom: ByteOrderMark,
+	pub description_bom: ByteOrderMark,
+}
+
+impl ID3GeneralObjectFrame {
+	pub fn bytes(&self, version: u8, text_encoding: TextEncoding) -> Vec<u8> {
+		// Software such as DJ applications may check the bytes, so keep the encoding that was read where possible
+		let encoding = keep_encoding(
+			version,
+			text_encoding,
+			self.encoding,
+			&[&self.filename, &self.description],
+		);
+		let encode = |s: &str, bom: ByteOrderMark| match encoding {
+			1 => bom.encode(s),
+			_ => encode_text(s, encoding),
+		};
+		let mut ret = Vec::<u8>::with_capacity(
+			1 + self.mime.len() + 1 + self.filename.len() + 1 + self.description.len() + 1 + self.data.len(),
+		);
 		ret.push(encoding);
-		ret.extend(self.data.as_bytes());
+		ret.extend(encode_text(&self.mime, 0));
+		ret.push(0);
+		ret.extend(encode(&self.filename, self.filename_bom));
+		ret.extend(terminator(encoding));
+		ret.extend(encode(&self.description, self.description_bom));
+		ret.extend(terminator(encoding));
+		ret.extend(&self.data);
 		ret
 	}
 }
 
+/// The byte order mark of a UTF-16 string (encoding 1), kept where the bytes of a frame should be written back as they
+/// were read.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ByteOrderMark {
+	/// FF FE
+	#[default]
+	LittleEndian,
+	/// FE FF
+	BigEndian,
+	/// An empty string with no byte order mark. A string which isn't empty is written little-endian.
+	Omitted,
+}
+
+impl ByteOrderMark {
+	/// Encode `s` as UTF-16 with this byte order mark, without a terminator.
+	pub fn encode(self, s: &str) -> Vec<u8> {
+		match self {
+			ByteOrderMark::BigEndian => [0xFE, 0xFF].into_iter().chain(encode_text(s, 2)).collect(),
+			ByteOrderMark::Omitted if s.is_empty() => Vec::new(),
+			_ => encode_text(s, 1),
+		}
+	}
+}
+
+/// Relative volume adjustment frame (RVA2). The identification says what the adjustment is for, such as "track" or
+/// "album" for ReplayGain.
+#[derive(Clone)]
+pub struct ID3RelativeVolumeFrame {
+	pub identification: String,
+	pub channels: Vec<ID3ChannelVolume>,
+}
+
+impl ID3RelativeVolumeFrame {
+	pub fn bytes(&self) -> Vec<u8> {
+		let mut ret = Vec::<u8>::new();
+		ret.extend(encode_text(&self.identification, 0));
+		ret.push(0);
+		for channel in &self.channels {
+			ret.push(channel.channel_type);
+			ret.extend(channel.adjustment.to_be_bytes());
+			ret.push(channel.peak_bits);
+			ret.extend(&channel.peak);
+		}
+		ret
+	}
+}
+
+#[derive(Clone)]
+pub struct ID3ChannelVolume {
+	/// 0: other, 1: master volume, 2: front right, 3: front left, 4: back right, 5: back left, 6: front centre,
+	/// 7: back centre, 8: subwoofer
+	pub channel_type: u8,
+	/// Fixed point dB, in units of 1/512 dB
+	pub adjustment: i16,
+	pub peak_bits: u8,
+	/// The peak volume as a big-endian integer of `peak_bits` bits, rounded up to whole bytes
+	pub peak: Vec<u8>,
+}
+
+impl ID3ChannelVolume {
+	pub fn gain_db(&self) -> f32 {
+		self.adjustment as f32 / 512.0
+	}
+	/// Convert a gain in dB to the fixed point adjustment, saturating at the limits of about ±64 dB.
+	pub fn adjustment_from_db(db: f32) -> i16 {
+		(db * 512.0).round().clamp(i16::MIN as f32, i16::MAX as f32) as i16
+	}
+	/// The peak as a linear amplitude, where 1.0 is full scale.
+	pub fn peak_ratio(&self) -> Option<f32> {
+		if self.peak_bits == 0 || self.peak.is_empty() || self.peak.len() > 8 {
+			return None;
+		}
+		let value = self.peak.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64);
+		Some((value as f64 / 2f64.powi(self.peak_bits as i32 - 1)) as f32)
+	}
+	/// Encode a linear peak amplitude in 16 bits, saturating just below 2.0.
+	pub fn peak_from_ratio(peak: f32) -> (u8, Vec<u8>) {
+		let value = (peak * 32768.0).round().clamp(0.0, u16::MAX as f32) as u16;
+		(16, value.to_be_bytes().to_vec())
+	}
+}
+
 #[derive(Clone)]
 pub struct ID3PictureFrame {
 	pub mime: String,
@@ -238,21 +983,18 @@ pub fn pic_type_name(b: u8) -> Option<&'static str> {
 }
 
 impl ID3PictureFrame {
-	pub fn bytes(&self) -> Vec<u8> {
-		let encoding = if self.description.chars().all(|x| x.is_ascii()) && self.mime.chars().all(|x| x.is_ascii()) {
-			0
-		} else {
-			3
-		};
+	pub fn bytes(&self, version: u8, text_encoding: TextEncoding) -> Vec<u8> {
+		let encoding = text_encoding.choose(version, &[&self.description]);
 
 		let mut ret =
 			Vec::<u8>::with_capacity(1 + self.mime.len() + 1 + 1 + self.description.len() + 1 + self.data.len());
 		ret.push(encoding);
+		// The MIME type is always ISO-8859-1
 		ret.extend(self.mime.as_bytes());
 		ret.push(0);
 		ret.push(self.pic_type);
-		ret.extend(self.description.as_bytes());
-		ret.push(0);
+		ret.extend(encode_text(&self.description, encoding));
+		ret.extend(terminator(encoding));
 		ret.extend(&self.data);
 		ret
 	}
@@ -268,22 +1010,556 @@ pub struct ID3CommentFrame {
 }
 
 impl ID3CommentFrame {
-	pub fn bytes(&self) -> Vec<u8> {
-		let encoding = if self.content_desc.chars().all(|x| x.is_ascii()) && self.text.chars().all(|x| x.is_ascii()) {
-			0
-		} else {
-			3
-		};
+	pub fn bytes(&self, version: u8, text_encoding: TextEncoding) -> Vec<u8> {
+		let encoding = text_encoding.choose(version, &[&self.content_desc, &self.text]);
 		let mut ret = Vec::<u8>::with_capacity(1 + 3 + self.content_desc.len() + 1 + self.text.len());
 		ret.push(encoding);
 		ret.extend(&self.language);
-		ret.extend(self.content_desc.as_bytes());
-		ret.push(0);
-		ret.extend(self.text.as_bytes());
+		ret.extend(encode_text(&self.content_desc, encoding));
+		ret.extend(terminator(encoding));
+		ret.extend(encode_text(&self.text, encoding));
 		ret
 	}
 }
 
+/// The text encoding to write. ID3v2.3 has only ISO-8859-1 and UTF-16 with a byte order mark, so UTF-16BE and UTF-8
+/// are written as UTF-16 there. Text which ISO-8859-1 can't represent is written as UTF-16 for ID3v2.3 and UTF-8 for
+/// ID3v2.4.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextEncoding {
+	#[default]
+	Latin1,
+	Utf16,
+	Utf16BE,
+	Utf8,
+}
+
+impl TextEncoding {
+	/// The encoding byte for a frame of ID3v2.`version` containing `strings`.
+	pub fn choose(self, version: u8, strings: &[&str]) -> u8 {
+		match self {
+			TextEncoding::Latin1 if strings.iter().all(|s| is_latin1(s)) => 0,
+			TextEncoding::Utf16BE if version >= 4 => 2,
+			TextEncoding::Latin1 | TextEncoding::Utf8 if version >= 4 => 3,
+			_ => 1,
+		}
+	}
+}
+
+fn is_latin1(s: &str) -> bool {
+	s.chars().all(|c| (c as u32) < 0x100)
+}
+
+/// As `TextEncoding::choose`, but keeps `encoding` if it is valid for the version and can represent the strings.
+fn keep_encoding(version: u8, text_encoding: TextEncoding, encoding: u8, strings: &[&str]) -> u8 {
+	match encoding {
+		0 if strings.iter().all(|s| is_latin1(s)) => 0,
+		1 => 1,
+		2 | 3 if version >= 4 => encoding,
+		_ => text_encoding.choose(version, strings),
+	}
+}
+
+/// Encode `s` without a terminator. Characters which ISO-8859-1 can't represent are written as "?".
+fn encode_text(s: &str, encoding: u8) -> Vec<u8> {
+	match encoding {
+		0 => s
+			.chars()
+			.map(|c| if (c as u32) < 0x100 { c as u8 } else { b'?' })
+			.collect(),
+		1 => {
+			let mut ret = vec![0xFF, 0xFE];
+			ret.extend(s.encode_utf16().flat_map(|c| c.to_le_bytes()));
+			ret
+		}
+		2 => s.encode_utf16().flat_map(|c| c.to_be_bytes()).collect(),
+		_ => s.as_bytes().to_vec(),
+	}
+}
+
+fn terminator(encoding: u8) -> &'static [u8] {
+	match encoding {
+		1 | 2 => &[0, 0],
+		_ => &[0],
+	}
+}
+
+fn text_frame(id: &[u8; 4], values: Vec<String>) -> ID3Frame {
+	ID3Frame {
+		id: *id,
+		flags: ID3FrameFlags::default(),
+		data: ID3FrameType::Text(ID3TextFrame { values, encoding: 0 }),
+	}
+}
+
+/// Text information frames, which are all T*** frames except TXXX.
+pub fn is_text_id(id: &[u8; 4]) -> bool {
+	id[0] == b'T' && id != b"TXXX"
+}
+
+/// Text frames whose values ID3v2.3 separates with "/".
+pub fn is_slash_separated(id: &[u8; 4]) -> bool {
+	matches!(id, b"TCOM" | b"TEXT" | b"TOLY" | b"TOPE" | b"TPE1")
+}
+
+/// Convert ID3v2.4 frames to their ID3v2.3 equivalents:
+/// - TDRC is split into TYER, TDAT (DDMM) and TIME (HHMM)
+/// - TDOR becomes TORY
+/// - TIPL and TMCL are combined into IPLS
+/// - TSOA, TSOP and TSOT become the unofficial XSOA, XSOP and XSOT frames
+/// - Text frames with several values have them joined with "/", as ID3v2.3 only allows one string
+/// - RVA2 becomes RVAD and EQU2 becomes EQUA. ID3v2.3 allows only one of each, so the adjustment for the whole track is
+///   used: the one identified as "track" or with no identification, or else the first.
+///
+/// Frames that only exist in ID3v2.4 and have no equivalent are dropped. Text frames which weren't parsed are parsed
+/// so their text can be re-encoded, and dropped if they aren't valid.
+pub fn downgrade_to_v23(frames: &[ID3Frame]) -> Vec<ID3Frame> {
+	let mut ret = Vec::with_capacity(frames.len());
+	let mut people = Vec::<String>::new();
+	let for_track = |id: &[u8; 4]| {
+		frames
+			.iter()
+			.enumerate()
+			.filter(|(_, f)| &f.id == id)
+			.min_by_key(|(_, f)| !matches!(adjustment_identification(f).as_deref(), Some("" | "track")))
+			.map(|(i, _)| i)
+	};
+	let (rva2, equ2) = (for_track(b"RVA2"), for_track(b"EQU2"));
+	for (i, frame) in frames.iter().enumerate() {
+		let converted = match &frame.data {
+			ID3FrameType::RelativeVolume(f) if Some(i) == rva2 => Some((*b"RVAD", downgrade_rva2(f))),
+			ID3FrameType::Raw(data) if Some(i) == equ2 => downgrade_equ2(data).map(|x| (*b"EQUA", x)),
+			_ => None,
+		};
+		if let Some((id, data)) = converted {
+			ret.push(ID3Frame {
+				id,
+				flags: frame.flags,
+				data: ID3FrameType::Raw(data),
+			});
+			continue;
+		}
+		let parsed: ID3Frame;
+		let frame = match &frame.data {
+			// Encrypted frames can't be parsed, so are kept as they are
+			ID3FrameType::Raw(data) if is_text_id(&frame.id) && frame.flags.encryption.is_none() => {
+				match crate::handle_t(data, &frame.id, 4) {
+					Ok(f) => {
+						parsed = ID3Frame {
+							data: ID3FrameType::Text(f),
+							..frame.clone()
+						};
+						&parsed
+					}
+					Err(_) => continue,
+				}
+			}
+			_ => frame,
+		};
+		let (values, text) = match &frame.data {
+			ID3FrameType::Text(f) => (&f.values, f.values.join("/")),
+			_ => {
+				ret.push(frame.clone());
+				continue;
+			}
+		};
+		let text = text.as_str();
+		match &frame.id {
+			b"TDRC" => {
+				// yyyy-MM-ddTHH:mm:ss, where everything after the year is optional
+				let (date, time) = text.split_once('T').unwrap_or((text, ""));
+				let mut date_parts = date.split('-');
+				let mut time_parts = time.split(':');
+				if let Some(year) = date_parts.next() {
+					ret.push(text_frame(b"TYER", vec![year.to_string()]));
+				}
+				if let (Some(month), Some(day)) = (date_parts.next(), date_parts.next()) {
+					ret.push(text_frame(b"TDAT", vec![day.to_string() + month]));
+				}
+				if let (Some(hour), Some(minute)) = (time_parts.next(), time_parts.next()) {
+					ret.push(text_frame(b"TIME", vec![hour.to_string() + minute]));
+				}
+			}
+			b"TDOR" => ret.push(text_frame(b"TORY", vec![text.chars().take(4).collect()])),
+			// Both are lists of involvement/name pairs, which IPLS also separates with the terminator
+			b"TIPL" | b"TMCL" => people.extend(values.iter().cloned()),
+			b"TSOA" => ret.push(text_frame(b"XSOA", vec![text.to_string()])),
+			b"TSOP" => ret.push(text_frame(b"XSOP", vec![text.to_string()])),
+			b"TSOT" => ret.push(text_frame(b"XSOT", vec![text.to_string()])),
+			b"TDEN" | b"TDRL" | b"TDTG" | b"TMOO" | b"TPRO" | b"TSST" => {}
+			_ if values.len() > 1 => ret.push(ID3Frame {
+				data: ID3FrameType::Text(ID3TextFrame {
+					values: vec![text.to_string()],
+					encoding: 0,
+				}),
+				..frame.clone()
+			}),
+			_ => ret.push(frame.clone()),
+		}
+	}
+	if !people.is_empty() {
+		ret.push(text_frame(b"IPLS", people));
+	}
+	// Binary frames introduced in ID3v2.4
+	ret.retain(|f| !matches!(&f.id, b"ASPI" | b"EQU2" | b"RVA2" | b"SEEK" | b"SIGN"));
+	ret
+}
+
+/// Convert frames read from an ID3v2.3 (or ID3v2.2) tag to their ID3v2.4 equivalents:
+/// - TYER, TDAT (DDMM), TIME (HHMM) are combined into TDRC. TRDA gives the year if there is no TYER.
+/// - TORY becomes TDOR
+/// - IPLS becomes TIPL
+/// - EQUA becomes EQU2 and RVAD becomes RVA2
+/// - The unofficial XSOA, XSOP and XSOT frames become TSOA, TSOP and TSOT
+///
+/// Returns the converted frames, and separately the ID3v2.3 frames which couldn't be converted, such as a TDAT
+/// without a year or an RVAD with more than 16 bits per value.
+pub fn upgrade_to_v24(frames: Vec<ID3Frame>) -> (Vec<ID3Frame>, Vec<ID3Frame>) {
+	let text = |id: &[u8; 4]| {
+		frames.iter().find_map(|f| match &f.data {
+			ID3FrameType::Text(t) if &f.id == id => t.values.first().map(|v| v.trim().to_string()),
+			_ => None,
+		})
+	};
+	let is_digits = |s: &str, len: usize| s.len() == len && s.bytes().all(|b| b.is_ascii_digit());
+	// Each part is only used if the ones before it are. A tag which already has TDRC keeps it.
+	let has_tdrc = frames.iter().any(|f| &f.id == b"TDRC");
+	let tyer = text(b"TYER").filter(|x| !has_tdrc && is_digits(x, 4));
+	let trda = text(b"TRDA")
+		.map(|x| x.chars().take(4).collect::<String>())
+		.filter(|x| !has_tdrc && tyer.is_none() && is_digits(x, 4));
+	let year = tyer.clone().or(trda.clone());
+	let tdat = text(b"TDAT").filter(|x| {
+		year.is_some()
+			&& is_digits(x, 4)
+			&& (1..=31).contains(&x[0..2].parse::<u8>().unwrap())
+			&& (1..=12).contains(&x[2..4].parse::<u8>().unwrap())
+	});
+	let time = text(b"TIME").filter(|x| {
+		tdat.is_some() && is_digits(x, 4) && x[0..2].parse::<u8>().unwrap() < 24 && x[2..4].parse::<u8>().unwrap() < 60
+	});
+	let mut date = year.clone();
+	if let (Some(date), Some(tdat)) = (&mut date, &tdat) {
+		*date += &format!("-{}-{}", &tdat[2..4], &tdat[0..2]);
+		if let Some(time) = &time {
+			*date += &format!("T{}:{}", &time[0..2], &time[2..4]);
+		}
+	}
+
+	let mut ret = Vec::with_capacity(frames.len());
+	let mut unconverted = Vec::new();
+	for frame in frames {
+		let used = match &frame.id {
+			b"TYER" => tyer.is_some(),
+			b"TRDA" => trda.is_some(),
+			b"TDAT" => tdat.is_some(),
+			b"TIME" => time.is_some(),
+			_ => {
+				let converted = match (&frame.id, &frame.data) {
+					(b"TORY", ID3FrameType::Text(_)) => Some((*b"TDOR", frame.data.clone())),
+					(b"IPLS", ID3FrameType::Text(_)) => Some((*b"TIPL", frame.data.clone())),
+					(b"XSOA", ID3FrameType::Text(_)) => Some((*b"TSOA", frame.data.clone())),
+					(b"XSOP", ID3FrameType::Text(_)) => Some((*b"TSOP", frame.data.clone())),
+					(b"XSOT", ID3FrameType::Text(_)) => Some((*b"TSOT", frame.data.clone())),
+					(b"EQUA", ID3FrameType::Raw(data)) => upgrade_equa(data).map(|x| (*b"EQU2", ID3FrameType::Raw(x))),
+					(b"RVAD", ID3FrameType::Raw(data)) => {
+						upgrade_rvad(data).map(|x| (*b"RVA2", ID3FrameType::RelativeVolume(x)))
+					}
+					(b"TORY" | b"IPLS" | b"XSOA" | b"XSOP" | b"XSOT" | b"EQUA" | b"RVAD", _) => None,
+					_ => {
+						ret.push(frame);
+						continue;
+					}
+				};
+				match converted {
+					Some((id, data)) => ret.push(ID3Frame {
+						id,
+						flags: frame.flags,
+						data,
+					}),
+					None => unconverted.push(frame),
+				}
+				continue;
+			}
+		};
+		if !used {
+			unconverted.push(frame);
+		} else if let Some(date) = date.take() {
+			// In place of the first of the date frames
+			ret.push(text_frame(b"TDRC", vec![date]));
+		}
+	}
+	(ret, unconverted)
+}
+
+/// The identification of an RVA2 or EQU2 frame.
+fn adjustment_identification(frame: &ID3Frame) -> Option<String> {
+	match &frame.data {
+		ID3FrameType::RelativeVolume(f) => Some(f.identification.clone()),
+		// EQU2 starts with the interpolation method
+		ID3FrameType::Raw(data) => {
+			let identification = data.get(1..)?;
+			let end = identification.iter().position(|b| *b == 0)?;
+			Some(identification[..end].iter().map(|b| *b as char).collect())
+		}
+		_ => None,
+	}
+}
+
+/// The inverse of `adjustment_db` for 16 bit values: whether the volume is increased, and by what fraction.
+fn adjustment_fraction(db: f32) -> (bool, u16) {
+	let factor = 10f64.powf(db as f64 / 20.0);
+	let value = ((factor - 1.0).abs() * 65536.0).round().min(u16::MAX as f64);
+	(factor >= 1.0, value as u16)
+}
+
+/// Convert an RVA2 frame to RVAD content with 16 bit values. A master volume adjustment is used for the front channels
+/// unless they have their own.
+fn downgrade_rva2(rva2: &ID3RelativeVolumeFrame) -> Vec<u8> {
+	let find = |channel_type: u8| rva2.channels.iter().find(|c| c.channel_type == channel_type);
+	// In the order of the increment bits: right, left, right back, left back, centre and bass
+	let channels = [2, 3, 4, 5, 6, 8].map(|channel_type| match channel_type {
+		2 | 3 => find(channel_type).or(find(1)),
+		_ => find(channel_type),
+	});
+	// Each group of values is only written if it or a later one is used
+	let groups = [0..2, 2..4, 4..5, 5..6];
+	let used = channels.iter().rposition(Option::is_some).unwrap_or(0);
+	let mut increments = 0;
+	let mut values = Vec::new();
+	for group in groups.into_iter().filter(|group| group.start <= used) {
+		let mut peaks = Vec::new();
+		for i in group {
+			let (increment, value) = channels[i].map_or((true, 0), |c| adjustment_fraction(c.gain_db()));
+			increments |= (increment as u8) << i;
+			values.extend(value.to_be_bytes());
+			let peak = channels[i].and_then(ID3ChannelVolume::peak_ratio).unwrap_or(0.0);
+			peaks.extend(ID3ChannelVolume::peak_from_ratio(peak).1);
+		}
+		values.extend(peaks);
+	}
+	[vec![increments, 16], values].concat()
+}
+
+/// Convert EQU2 content to EQUA content with 16 bit adjustments. The interpolation method is lost.
+fn downgrade_equ2(data: &[u8]) -> Option<Vec<u8>> {
+	let start = 1 + data.get(1..)?.iter().position(|b| *b == 0)? + 1;
+	let points = &data[start..];
+	if !points.len().is_multiple_of(4) {
+		return None;
+	}
+	let mut ret = vec![16];
+	for point in points.chunks(4) {
+		// EQU2 frequencies are in units of 1/2 Hz
+		let frequency = u16::from_be_bytes([point[0], point[1]]) / 2;
+		let db = i16::from_be_bytes([point[2], point[3]]) as f32 / 512.0;
+		let (increment, value) = adjustment_fraction(db);
+		ret.extend((frequency | (increment as u16) << 15).to_be_bytes());
+		ret.extend(value.to_be_bytes());
+	}
+	Some(ret)
+}
+
+/// An ID3v2.3 volume adjustment of `bits` bits is a fraction of full volume to add or remove.
+fn adjustment_db(increment: bool, value: u64, bits: u8) -> Option<f32> {
+	let fraction = value as f64 / 2f64.powi(bits as i32);
+	let factor = if increment { 1.0 + fraction } else { 1.0 - fraction };
+	if factor <= 0.0 {
+		return None;
+	}
+	Some((20.0 * factor.log10()) as f32)
+}
+
+fn read_be(data: &[u8]) -> u64 {
+	data.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64)
+}
+
+/// Convert EQUA content to EQU2 content with linear interpolation.
+fn upgrade_equa(data: &[u8]) -> Option<Vec<u8>> {
+	let bits = *data.first()?;
+	if bits == 0 || bits > 32 {
+		return None;
+	}
+	let value_len = (bits as usize).div_ceil(8);
+	// Interpolation method, then an empty identification
+	let mut ret = vec![1, 0];
+	for band in data[1..].chunks(2 + value_len) {
+		if band.len() != 2 + value_len {
+			return None;
+		}
+		let increment = band[0] & 0x80 != 0;
+		let frequency = u16::from_be_bytes([band[0] & 0x7F, band[1]]);
+		let db = adjustment_db(increment, read_be(&band[2..]), bits)?;
+		// EQU2 frequencies are in units of 1/2 Hz
+		ret.extend((frequency * 2).to_be_bytes());
+		ret.extend(ID3ChannelVolume::adjustment_from_db(db).to_be_bytes());
+	}
+	Some(ret)
+}
+
+/// Convert RVAD content: right, left, right back, left back, centre and bass volume changes, each followed by their
+/// peaks.
+fn upgrade_rvad(data: &[u8]) -> Option<ID3RelativeVolumeFrame> {
+	let (increments, bits) = (*data.first()?, *data.get(1)?);
+	if bits == 0 || bits > 64 {
+		return None;
+	}
+	let value_len = (bits as usize).div_ceil(8);
+	let values: Vec<&[u8]> = data[2..].chunks(value_len).collect();
+	if values.len() < 4 || values.last()?.len() != value_len {
+		return None;
+	}
+	// Values come in groups of volume changes then peaks: [R L pR pL] [RB LB pRB pLB] [C pC] [B pB]
+	let layout: [(usize, usize, u8); 6] = [(0, 2, 2), (1, 3, 3), (4, 6, 4), (5, 7, 5), (8, 9, 6), (10, 11, 8)];
+	let mut channels = Vec::new();
+	for (i, (change, peak, channel_type)) in layout.into_iter().enumerate() {
+		let (Some(change), Some(peak)) = (values.get(change), values.get(peak)) else {
+			break;
+		};
+		let db = adjustment_db(increments & (1 << i) != 0, read_be(change), bits)?;
+		channels.push(ID3ChannelVolume {
+			channel_type,
+			adjustment: ID3ChannelVolume::adjustment_from_db(db),
+			peak_bits: bits,
+			peak: peak.to_vec(),
+		});
+	}
+	Some(ID3RelativeVolumeFrame {
+		identification: String::new(),
+		channels,
+	})
+}
+
+/// The ID3v2.4 frame ID corresponding to an ID3v2.2 frame ID. Frames which have no equivalent (or whose format
+/// changed such that the content can't be carried over) return None.
+pub fn upgrade_v22_id(id: &[u8; 3]) -> Option<[u8; 4]> {
+	let new_id = match id {
+		b"BUF" => b"RBUF",
+		b"CNT" => b"PCNT",
+		b"COM" => b"COMM",
+		b"CRA" => b"AENC",
+		b"ETC" => b"ETCO",
+		b"EQU" => b"EQUA",
+		b"GEO" => b"GEOB",
+		b"IPL" => b"IPLS",
+		b"MCI" => b"MCDI",
+		b"MLL" => b"MLLT",
+		b"PIC" => b"APIC",
+		b"POP" => b"POPM",
+		b"REV" => b"RVRB",
+		b"RVA" => b"RVAD",
+		b"SLT" => b"SYLT",
+		b"STC" => b"SYTC",
+		b"TAL" => b"TALB",
+		b"TBP" => b"TBPM",
+		b"TCM" => b"TCOM",
+		b"TCO" => b"TCON",
+		b"TCP" => b"TCMP",
+		b"TCR" => b"TCOP",
+		b"TDA" => b"TDAT",
+		b"TDY" => b"TDLY",
+		b"TEN" => b"TENC",
+		b"TFT" => b"TFLT",
+		b"TIM" => b"TIME",
+		b"TKE" => b"TKEY",
+		b"TLA" => b"TLAN",
+		b"TLE" => b"TLEN",
+		b"TMT" => b"TMED",
+		b"TOA" => b"TOPE",
+		b"TOF" => b"TOFN",
+		b"TOL" => b"TOLY",
+		b"TOR" => b"TORY",
+		b"TOT" => b"TOAL",
+		b"TP1" => b"TPE1",
+		b"TP2" => b"TPE2",
+		b"TP3" => b"TPE3",
+		b"TP4" => b"TPE4",
+		b"TPA" => b"TPOS",
+		b"TPB" => b"TPUB",
+		b"TRC" => b"TSRC",
+		b"TRD" => b"TRDA",
+		b"TRK" => b"TRCK",
+		b"TS2" => b"TSO2",
+		b"TSA" => b"TSOA",
+		b"TSC" => b"TSOC",
+		b"TSI" => b"TSIZ",
+		b"TSP" => b"TSOP",
+		b"TSS" => b"TSSE",
+		b"TST" => b"TSOT",
+		b"TT1" => b"TIT1",
+		b"TT2" => b"TIT2",
+		b"TT3" => b"TIT3",
+		b"TXT" => b"TEXT",
+		b"TXX" => b"TXXX",
+		b"TYE" => b"TYER",
+		b"UFI" => b"UFID",
+		b"ULT" => b"USLT",
+		b"WAF" => b"WOAF",
+		b"WAR" => b"WOAR",
+		b"WAS" => b"WOAS",
+		b"WCM" => b"WCOM",
+		b"WCP" => b"WCOP",
+		b"WPB" => b"WPUB",
+		b"WXX" => b"WXXX",
+		// CRM (encrypted meta frame) has no counterpart and LNK refers to frames by their 3 character ID
+		_ => return None,
+	};
+	Some(*new_id)
+}
+
+/// zlib compression, as used by compressed frames.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+	let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+	// Writing to a Vec can't fail
+	encoder.write_all(data).unwrap();
+	encoder.finish().unwrap()
+}
+
+/// CRC-32 as defined in ISO-3309, which is used by the ID3 extended header.
+pub fn crc32(data: &[u8]) -> u32 {
+	let mut crc = 0xFFFF_FFFFu32;
+	for b in data {
+		crc ^= *b as u32;
+		for _ in 0..8 {
+			crc = if crc & 1 != 0 {
+				(crc >> 1) ^ 0xEDB8_8320
+			} else {
+				crc >> 1
+			};
+		}
+	}
+	!crc
+}
+
+/// Apply the unsynchronisation scheme: insert a zero byte after every $FF which is followed by a byte that could be
+/// mistaken for an MPEG frame sync (%111xxxxx) or by $00, and after a trailing $FF.
+pub fn unsynchronise(data: &[u8]) -> Vec<u8> {
+	let mut ret = Vec::<u8>::with_capacity(data.len() + data.len() / 64);
+	for (i, b) in data.iter().enumerate() {
+		ret.push(*b);
+		if *b == 0xFF {
+			match data.get(i + 1) {
+				Some(next) if *next != 0 && *next < 0b1110_0000 => {}
+				_ => ret.push(0),
+			}
+		}
+	}
+	ret
+}
+
+/// Reverse the unsynchronisation scheme by removing the zero byte from every $FF $00 pair.
+pub fn resynchronise(data: &[u8]) -> Vec<u8> {
+	let mut ret = Vec::<u8>::with_capacity(data.len());
+	let mut prev = 0;
+	for b in data {
+		if !(prev == 0xFF && *b == 0) {
+			ret.push(*b);
+		}
+		prev = *b;
+	}
+	ret
+}
+
 pub fn synchsafe_bytes(mut n: u32) -> [u8; 4] {
 	let mut b: [u8; 4] = [0, 0, 0, 0];
 	b[3] = (n % 128) as u8;
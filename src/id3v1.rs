@@ -0,0 +1,477 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::id3::{ID3CommentFrame, ID3Frame, ID3FrameFlags, ID3FrameType, ID3TextFrame};
+
+/// The 128 byte ID3v1 tag at the end of a file, along with the "TAG+" extended tag which may precede it.
+///
+/// Text is ISO-8859-1, padded with NULs or spaces. ID3v1.1 takes the last two bytes of the comment for a zero byte
+/// and the track number.
+#[derive(Clone, Default)]
+pub struct ID3v1Tag {
+	pub title: String,
+	pub artist: String,
+	pub album: String,
+	pub year: String,
+	pub comment: String,
+	/// ID3v1.1 only
+	pub track: Option<u8>,
+	/// Index into the genre table, or 255 for none
+	pub genre: u8,
+	pub extended: Option<ID3v1ExtendedTag>,
+}
+
+/// The 227 byte "TAG+" block. Its title, artist and album continue those of the ID3v1 tag.
+#[derive(Clone, Default)]
+pub struct ID3v1ExtendedTag {
+	pub title: String,
+	pub artist: String,
+	pub album: String,
+	/// 0: unset, 1: slow, 2: medium, 3: fast, 4: hardcore
+	pub speed: u8,
+	/// Free-text genre
+	pub genre: String,
+	/// "mmm:ss"
+	pub start_time: String,
+	pub end_time: String,
+}
+
+const SIZE: usize = 128;
+const EXTENDED_SIZE: usize = 227;
+
+impl ID3v1Tag {
+	/// Read the ID3v1 tag at the end of `content`, if there is one.
+	pub fn read(content: &[u8]) -> Option<ID3v1Tag> {
+		let tag = content.get(content.len().checked_sub(SIZE)?..)?;
+		if &tag[0..3] != b"TAG" {
+			return None;
+		}
+		// A zero byte before a non-zero last byte of the comment means it holds an ID3v1.1 track number
+		let (comment, track) = if tag[125] == 0 && tag[126] != 0 {
+			(&tag[97..125], Some(tag[126]))
+		} else {
+			(&tag[97..127], None)
+		};
+		let extended = content
+			.len()
+			.checked_sub(SIZE + EXTENDED_SIZE)
+			.map(|start| &content[start..start + EXTENDED_SIZE])
+			.filter(|ext| &ext[0..4] == b"TAG+")
+			.map(|ext| ID3v1ExtendedTag {
+				title: decode(&ext[4..64]),
+				artist: decode(&ext[64..124]),
+				album: decode(&ext[124..184]),
+				speed: ext[184],
+				genre: decode(&ext[185..215]),
+				start_time: decode(&ext[215..221]),
+				end_time: decode(&ext[221..227]),
+			});
+
+		Some(ID3v1Tag {
+			title: decode(&tag[3..33]),
+			artist: decode(&tag[33..63]),
+			album: decode(&tag[63..93]),
+			year: decode(&tag[93..97]),
+			comment: decode(comment),
+			track,
+			genre: tag[127],
+			extended,
+		})
+	}
+
+	/// Read the ID3v1 tag at the end of `reader`, reading only the last bytes of the file.
+	pub fn read_from<R: Read + Seek>(reader: &mut R) -> std::io::Result<Option<ID3v1Tag>> {
+		let len = reader.seek(SeekFrom::End(0))?;
+		let tail_len = len.min((SIZE + EXTENDED_SIZE) as u64);
+		reader.seek(SeekFrom::Start(len - tail_len))?;
+		let mut tail = vec![0u8; tail_len as usize];
+		reader.read_exact(&mut tail)?;
+		Ok(ID3v1Tag::read(&tail))
+	}
+
+	/// The number of bytes the tag takes at the end of the file.
+	pub fn size(&self) -> usize {
+		if self.extended.is_some() {
+			SIZE + EXTENDED_SIZE
+		} else {
+			SIZE
+		}
+	}
+
+	pub fn bytes(&self) -> Vec<u8> {
+		let mut ret = Vec::<u8>::with_capacity(self.size());
+		if let Some(ext) = &self.extended {
+			ret.extend(b"TAG+");
+			ret.extend(encode(&ext.title, 60));
+			ret.extend(encode(&ext.artist, 60));
+			ret.extend(encode(&ext.album, 60));
+			ret.push(ext.speed);
+			ret.extend(encode(&ext.genre, 30));
+			ret.extend(encode(&ext.start_time, 6));
+			ret.extend(encode(&ext.end_time, 6));
+		}
+		ret.extend(b"TAG");
+		ret.extend(encode(&self.title, 30));
+		ret.extend(encode(&self.artist, 30));
+		ret.extend(encode(&self.album, 30));
+		ret.extend(encode(&self.year, 4));
+		match self.track {
+			Some(track) => {
+				ret.extend(encode(&self.comment, 28));
+				ret.push(0);
+				ret.push(track);
+			}
+			None => ret.extend(encode(&self.comment, 30)),
+		}
+		ret.push(self.genre);
+		ret
+	}
+
+	/// Build a tag from ID3v2 frames. Text longer than the ID3v1 fields continues in an extended tag.
+	pub fn from_frames(frames: &[ID3Frame]) -> ID3v1Tag {
+		let text = |id: &[u8; 4]| {
+			frames
+				.iter()
+				.find_map(|f| match &f.data {
+					ID3FrameType::Text(t) if f.id == *id => Some(t.values.join("/")),
+					_ => None,
+				})
+				.unwrap_or_default()
+		};
+		let comment = frames
+			.iter()
+			.filter_map(|f| match &f.data {
+				ID3FrameType::Comment(c) if &f.id == b"COMM" => Some(c),
+				_ => None,
+			})
+			// Prefer the comment without a description
+			.min_by_key(|c| !c.content_desc.is_empty())
+			.map(|c| c.text.clone())
+			.unwrap_or_default();
+		let track = text(b"TRCK")
+			.split('/')
+			.next()
+			.and_then(|t| t.trim().parse::<u8>().ok());
+		let genre_text = text(b"TCON");
+		let genre = parse_genre(&genre_text);
+
+		let title = text(b"TIT2");
+		let artist = text(b"TPE1");
+		let album = text(b"TALB");
+		let (title, title_ext) = split_at_byte(&title, 30);
+		let (artist, artist_ext) = split_at_byte(&artist, 30);
+		let (album, album_ext) = split_at_byte(&album, 30);
+		// The free-text genre is only worth an extended tag if it isn't in the table
+		let genre_ext = if genre == 255 {
+			genre_text.clone()
+		} else {
+			String::new()
+		};
+		let extended = if title_ext.is_empty() && artist_ext.is_empty() && album_ext.is_empty() && genre_ext.is_empty() {
+			None
+		} else {
+			Some(ID3v1ExtendedTag {
+				title: title_ext.to_string(),
+				artist: artist_ext.to_string(),
+				album: album_ext.to_string(),
+				genre: genre_ext,
+				..Default::default()
+			})
+		};
+
+		ID3v1Tag {
+			title: title.to_string(),
+			artist: artist.to_string(),
+			album: album.to_string(),
+			year: text(b"TDRC").chars().take(4).collect(),
+			comment,
+			track,
+			genre,
+			extended,
+		}
+	}
+
+	/// Equivalent ID3v2.4 frames for the fields which are set.
+	pub fn frames(&self) -> Vec<ID3Frame> {
+		let mut ret = Vec::new();
+		let ext = self.extended.clone().unwrap_or_default();
+		let mut push_text = |id: &[u8; 4], value: String| {
+			if !value.is_empty() {
+				ret.push(ID3Frame {
+					id: *id,
+					flags: ID3FrameFlags::default(),
+					data: ID3FrameType::Text(ID3TextFrame {
+						values: vec![value],
+						encoding: 0,
+					}),
+				});
+			}
+		};
+		push_text(b"TIT2", self.title.clone() + &ext.title);
+		push_text(b"TPE1", self.artist.clone() + &ext.artist);
+		push_text(b"TALB", self.album.clone() + &ext.album);
+		push_text(b"TDRC", self.year.clone());
+		push_text(b"TRCK", self.track.map(|t| t.to_string()).unwrap_or_default());
+		let genre = if ext.genre.is_empty() {
+			genre_name(self.genre).unwrap_or_default().to_string()
+		} else {
+			ext.genre.clone()
+		};
+		push_text(b"TCON", genre);
+		if !self.comment.is_empty() {
+			ret.push(ID3Frame {
+				id: *b"COMM",
+				flags: ID3FrameFlags::default(),
+				data: ID3FrameType::Comment(ID3CommentFrame {
+					language: *b"eng",
+					content_desc: String::new(),
+					text: self.comment.clone(),
+					encoding: 0,
+				}),
+			});
+		}
+		ret
+	}
+}
+
+/// ISO-8859-1 text padded with NULs or spaces.
+fn decode(data: &[u8]) -> String {
+	let end = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+	data[..end]
+		.iter()
+		.map(|b| *b as char)
+		.collect::<String>()
+		.trim_end()
+		.to_string()
+}
+
+/// ISO-8859-1 text NUL padded to `len` bytes. Characters outside ISO-8859-1 are replaced with '?'.
+fn encode(s: &str, len: usize) -> Vec<u8> {
+	let mut ret: Vec<u8> = s
+		.chars()
+		.map(|c| if (c as u32) < 256 { c as u8 } else { b'?' })
+		.take(len)
+		.collect();
+	ret.resize(len, 0);
+	ret
+}
+
+/// Split after `len` characters, as each character is one byte in ISO-8859-1.
+fn split_at_byte(s: &str, len: usize) -> (&str, &str) {
+	match s.char_indices().nth(len) {
+		Some((i, _)) => s.split_at(i),
+		None => (s, ""),
+	}
+}
+
+/// The genre number of ID3v2 TCON text, which may be a name, a number, or a number in parentheses such as "(17)".
+pub fn parse_genre(text: &str) -> u8 {
+	let text = text.trim();
+	let number = text.strip_prefix('(').and_then(|t| t.split(')').next()).unwrap_or(text);
+	if let Ok(n) = number.parse::<u8>() {
+		return n;
+	}
+	GENRES
+		.iter()
+		.position(|g| g.eq_ignore_ascii_case(text))
+		.map_or(255, |n| n as u8)
+}
+
+pub fn genre_name(genre: u8) -> Option<&'static str> {
+	GENRES.get(genre as usize).copied()
+}
+
+/// The ID3v1 genres, including the Winamp extensions.
+pub const GENRES: [&str; 192] = [
+	"Blues",
+	"Classic Rock",
+	"Country",
+	"Dance",
+	"Disco",
+	"Funk",
+	"Grunge",
+	"Hip-Hop",
+	"Jazz",
+	"Metal",
+	"New Age",
+	"Oldies",
+	"Other",
+	"Pop",
+	"R&B",
+	"Rap",
+	"Reggae",
+	"Rock",
+	"Techno",
+	"Industrial",
+	"Alternative",
+	"Ska",
+	"Death Metal",
+	"Pranks",
+	"Soundtrack",
+	"Euro-Techno",
+	"Ambient",
+	"Trip-Hop",
+	"Vocal",
+	"Jazz+Funk",
+	"Fusion",
+	"Trance",
+	"Classical",
+	"Instrumental",
+	"Acid",
+	"House",
+	"Game",
+	"Sound Clip",
+	"Gospel",
+	"Noise",
+	"AlternRock",
+	"Bass",
+	"Soul",
+	"Punk",
+	"Space",
+	"Meditative",
+	"Instrumental Pop",
+	"Instrumental Rock",
+	"Ethnic",
+	"Gothic",
+	"Darkwave",
+	"Techno-Industrial",
+	"Electronic",
+	"Pop-Folk",
+	"Eurodance",
+	"Dream",
+	"Southern Rock",
+	"Comedy",
+	"Cult",
+	"Gangsta",
+	"Top 40",
+	"Christian Rap",
+	"Pop/Funk",
+	"Jungle",
+	"Native American",
+	"Cabaret",
+	"New Wave",
+	"Psychadelic",
+	"Rave",
+	"Showtunes",
+	"Trailer",
+	"Lo-Fi",
+	"Tribal",
+	"Acid Punk",
+	"Acid Jazz",
+	"Polka",
+	"Retro",
+	"Musical",
+	"Rock & Roll",
+	"Hard Rock",
+	"Folk",
+	"Folk-Rock",
+	"National Folk",
+	"Swing",
+	"Fast Fusion",
+	"Bebob",
+	"Latin",
+	"Revival",
+	"Celtic",
+	"Bluegrass",
+	"Avantgarde",
+	"Gothic Rock",
+	"Progressive Rock",
+	"Psychedelic Rock",
+	"Symphonic Rock",
+	"Slow Rock",
+	"Big Band",
+	"Chorus",
+	"Easy Listening",
+	"Acoustic",
+	"Humour",
+	"Speech",
+	"Chanson",
+	"Opera",
+	"Chamber Music",
+	"Sonata",
+	"Symphony",
+	"Booty Bass",
+	"Primus",
+	"Porn Groove",
+	"Satire",
+	"Slow Jam",
+	"Club",
+	"Tango",
+	"Samba",
+	"Folklore",
+	"Ballad",
+	"Power Ballad",
+	"Rhythmic Soul",
+	"Freestyle",
+	"Duet",
+	"Punk Rock",
+	"Drum Solo",
+	"A capella",
+	"Euro-House",
+	"Dance Hall",
+	"Goa",
+	"Drum & Bass",
+	"Club-House",
+	"Hardcore",
+	"Terror",
+	"Indie",
+	"BritPop",
+	"Afro-Punk",
+	"Polsk Punk",
+	"Beat",
+	"Christian Gangsta Rap",
+	"Heavy Metal",
+	"Black Metal",
+	"Crossover",
+	"Contemporary Christian",
+	"Christian Rock",
+	"Merengue",
+	"Salsa",
+	"Thrash Metal",
+	"Anime",
+	"JPop",
+	"Synthpop",
+	"Abstract",
+	"Art Rock",
+	"Baroque",
+	"Bhangra",
+	"Big Beat",
+	"Breakbeat",
+	"Chillout",
+	"Downtempo",
+	"Dub",
+	"EBM",
+	"Eclectic",
+	"Electro",
+	"Electroclash",
+	"Emo",
+	"Experimental",
+	"Garage",
+	"Global",
+	"IDM",
+	"Illbient",
+	"Industro-Goth",
+	"Jam Band",
+	"Krautrock",
+	"Leftfield",
+	"Lounge",
+	"Math Rock",
+	"New Romantic",
+	"Nu-Breakz",
+	"Post-Punk",
+	"Post-Rock",
+	"Psytrance",
+	"Shoegaze",
+	"Space Rock",
+	"Trop Rock",
+	"World Music",
+	"Neoclassical",
+	"Audiobook",
+	"Audio Theatre",
+	"Neue Deutsche Welle",
+	"Podcast",
+	"Indie Rock",
+	"G-Funk",
+	"Dubstep",
+	"Garage Rock",
+	"Psybient",
+];
@@ -1,392 +1,808 @@
-use core::{convert::TryInto, panic};
+use core::convert::TryInto;
+use std::{
+	borrow::Cow,
+	io::{Read, Seek, SeekFrom, Write},
+};
 
 use id3::ID3FrameType;
 
+pub mod ape;
+pub mod codepage;
+mod cursor;
+mod error;
 pub mod id3;
+pub mod id3v1;
 mod itunes;
+pub mod lrc;
 pub mod mp4;
+pub mod mpeg;
+pub mod replaygain;
 
-pub fn read_id3_frames(content: &[u8]) -> (Vec<id3::ID3Frame>, usize) {
-	// let s = std::str::from_utf8(&content[0..3]).unwrap();
-	// println!("Header:");
-	// println!("0-2: {}", s);
-	let major_version = content[3];
-	// println!("version: id3v2.{}.{}", major_version, content[4]);
-
-	// let flags = content[5];
-	// if flags != 0 {
-	// 	panic!("flags != 0: {}", flags);
-	// }
-	// println!("flags: {}", flags);
-	// let sz: u32 = &content[5..8].try_into().unwrap();
-	let arr: [u8; 4] = content[6..10].try_into().unwrap();
-	let id3_size: usize = id3::from_synchsafe(arr) as usize;
-	// let sz = u32::from_be_bytes(arr) as usize;
-	// println!("size: {}", id3_size);
-	// for content[0..3]
-
-	// let header = ID3Header {
-	// 	version_major: 4,
-	// 	version_minor: 0,
-	// 	flags: flags,
-	// 	size: 0,
-	// };
+pub use error::TagError;
 
-	let mut frames = Vec::<id3::ID3Frame>::new();
+use cursor::Cursor;
+
+/// The largest size a synchsafe tag size can give
+const MAX_TAG_SIZE: u64 = 1 << 28;
 
-	let mut tdat_month: Option<&str> = None;
-	let mut tdat_day: Option<&str> = None;
-	let mut tyer: Option<&str> = None;
+/// Read the frames of the ID3v2 tag at the start of `content`.
+///
+/// Returns the frames along with the total size of the tag in bytes (header, frames, padding and footer), which is
+/// the offset at which the audio data starts.
+pub fn read_id3_frames(content: &[u8]) -> Result<(Vec<id3::ID3Frame>, usize), TagError> {
+	let tag = read_id3_tag(content)?;
+	let tag_size = tag.size();
+	Ok((tag.frames, tag_size))
+}
+
+/// Read the frames of the ID3v2 tag at the start of `content` as `read_id3_frames`, decoding the text marked as
+/// ISO-8859-1 as `codepage` instead. `codepage::detect` guesses the codepage from the frames read as ISO-8859-1.
+pub fn read_id3_frames_in_codepage(
+	content: &[u8],
+	codepage: &'static codepage::Encoding,
+) -> Result<(Vec<id3::ID3Frame>, usize), TagError> {
+	let tag = read_id3_tag_in_codepage(content, codepage)?;
+	let tag_size = tag.size();
+	Ok((tag.frames, tag_size))
+}
+
+/// Read the ID3v2 tag at the start of `content`, including its header and extended header.
+///
+/// If the extended header has a CRC it is checked against the tag data.
+pub fn read_id3_tag(content: &[u8]) -> Result<id3::ID3v240Tag, TagError> {
+	let bytes: [u8; 10] = Cursor::new(content).array().map_err(|_| TagError::MissingHeader)?;
+	if &bytes[0..3] != b"ID3" {
+		return Err(TagError::MissingHeader);
+	}
+	let header = id3::ID3Header {
+		version_major: bytes[3],
+		version_minor: bytes[4],
+		flags: bytes[5],
+		size: id3::from_synchsafe([bytes[6], bytes[7], bytes[8], bytes[9]]),
+	};
+	let major_version = header.version_major;
+	// In ID3v2.2 this flag indicates a compression scheme that was never defined
+	if major_version == 2 && header.flags & 0b0100_0000 != 0 {
+		return Err(TagError::UnsupportedFlags {
+			frame: None,
+			offset: 5,
+			flags: [header.flags, 0],
+		});
+	}
+	let id3_size = 10 + header.size as usize;
+	let content = &content[..id3_size.min(content.len())];
+	// Before ID3v2.4 unsynchronisation is applied to the whole tag after the header
+	let resynchronised: Vec<u8>;
+	let mut removed = 0;
+	let content = if major_version <= 3 && header.unsynchronisation() {
+		resynchronised = [&content[..10], &id3::resynchronise(&content[10..])].concat();
+		removed = content.len() - resynchronised.len();
+		&resynchronised
+	} else {
+		content
+	};
 
 	let mut ix: usize = 10;
-	while ix < id3_size {
-		// println!("---------------- {} / {}", ix, id3_size);
-		if content[ix] == 0 && content[ix + 1] == 0 && content[ix + 2] == 0 && content[ix + 3] == 0 {
-			break;
+	let mut frames_end = content.len();
+	let extended_header = if header.extended_header() {
+		let (extended_header, size) = handle_extended_header(&content[ix..], major_version).map_err(|e| e.shifted(ix))?;
+		ix += size;
+		if major_version <= 3 {
+			frames_end = frames_end.saturating_sub(extended_header.padding_size as usize).max(ix);
+		}
+		if let Some(expected) = extended_header.crc {
+			// ID3v2.3 calculates the CRC over the frames only, ID3v2.4 includes the padding
+			let calculated = id3::crc32(&content[ix.min(frames_end)..frames_end]);
+			if calculated != expected {
+				return Err(TagError::CrcMismatch { expected, calculated });
+			}
 		}
-		let mut code = std::str::from_utf8(&content[ix..ix + 4]).unwrap();
-		ix += 4;
-		let sz = if major_version <= 3 {
-			u32::from_be_bytes(content[ix..ix + 4].try_into().unwrap()) as usize
+		Some(extended_header)
+	} else {
+		None
+	};
+	let content = &content[..frames_end];
+
+	let mut unconverted = Vec::new();
+	let (frames, ix) = read_frames(
+		content,
+		ix,
+		major_version,
+		header.unsynchronisation(),
+		false,
+		&mut unconverted,
+	)?;
+
+	// The padding is after the frames, so unaffected by unsynchronisation
+	let padding = id3_size.saturating_sub(ix + removed) as u32;
+	Ok(id3::ID3v240Tag {
+		has_footer: major_version >= 4 && header.footer_present(),
+		header,
+		extended_header,
+		frames,
+		unconverted,
+		padding,
+		text_encoding: id3::TextEncoding::default(),
+	})
+}
+
+/// Read the ID3v2 tag at the start of `content` as `read_id3_tag`, decoding the text marked as ISO-8859-1 as
+/// `codepage` instead.
+pub fn read_id3_tag_in_codepage(
+	content: &[u8],
+	codepage: &'static codepage::Encoding,
+) -> Result<id3::ID3v240Tag, TagError> {
+	let mut tag = read_id3_tag(content)?;
+	codepage::decode_frames(&mut tag.frames, codepage);
+	codepage::decode_frames(&mut tag.unconverted, codepage);
+	Ok(tag)
+}
+
+/// Read the frames of the ID3v2 tag at the start of `reader`, as `read_id3_frames`.
+pub fn read_id3_frames_from<R: Read + Seek>(reader: &mut R) -> Result<(Vec<id3::ID3Frame>, usize), TagError> {
+	let tag = read_id3_tag_from(reader)?;
+	let tag_size = tag.size();
+	Ok((tag.frames, tag_size))
+}
+
+/// Read the frames of the ID3v2 tag at the start of `reader`, as `read_id3_frames_in_codepage`.
+pub fn read_id3_frames_in_codepage_from<R: Read + Seek>(
+	reader: &mut R,
+	codepage: &'static codepage::Encoding,
+) -> Result<(Vec<id3::ID3Frame>, usize), TagError> {
+	let tag = read_id3_tag_in_codepage_from(reader, codepage)?;
+	let tag_size = tag.size();
+	Ok((tag.frames, tag_size))
+}
+
+/// Read the ID3v2 tag at the start of `reader`, as `read_id3_tag`. Only the header and the tag itself are read, not
+/// the rest of the file.
+pub fn read_id3_tag_from<R: Read + Seek>(reader: &mut R) -> Result<id3::ID3v240Tag, TagError> {
+	reader.seek(SeekFrom::Start(0))?;
+	let mut header = [0u8; 10];
+	match reader.read_exact(&mut header) {
+		Ok(_) => (),
+		Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Err(TagError::MissingHeader),
+		Err(e) => return Err(e.into()),
+	}
+	if &header[0..3] != b"ID3" {
+		return Err(TagError::MissingHeader);
+	}
+	let size = id3::from_synchsafe(header[6..10].try_into().unwrap()) as u64;
+	// The footer is not part of the size in the header
+	let footer = if header[3] >= 4 && header[5] & 0b0001_0000 != 0 {
+		10
+	} else {
+		0
+	};
+	let mut content = header.to_vec();
+	// A truncated tag is reported by the parser
+	reader.take(size + footer).read_to_end(&mut content)?;
+	read_id3_tag(&content)
+}
+
+/// Read the ID3v2 tag at the start of `reader`, as `read_id3_tag_in_codepage`.
+pub fn read_id3_tag_in_codepage_from<R: Read + Seek>(
+	reader: &mut R,
+	codepage: &'static codepage::Encoding,
+) -> Result<id3::ID3v240Tag, TagError> {
+	let mut tag = read_id3_tag_from(reader)?;
+	codepage::decode_frames(&mut tag.frames, codepage);
+	codepage::decode_frames(&mut tag.unconverted, codepage);
+	Ok(tag)
+}
+
+/// Copy the bytes from `start` to `end` of `reader` to `writer`, a buffer at a time, such as the audio between the
+/// tags of a file.
+pub fn copy_range<R: Read + Seek, W: Write>(
+	reader: &mut R,
+	writer: &mut W,
+	start: u64,
+	end: u64,
+) -> std::io::Result<u64> {
+	reader.seek(SeekFrom::Start(start))?;
+	let copied = std::io::copy(&mut reader.take(end.saturating_sub(start)), writer)?;
+	if copied < end.saturating_sub(start) {
+		return Err(std::io::ErrorKind::UnexpectedEof.into());
+	}
+	Ok(copied)
+}
+
+/// Read frames from `content`, starting at `ix`, until the end of the data or the start of the padding. Returns the
+/// frames and the offset at which reading stopped. This is also used for the frames `embedded` in CHAP and CTOC
+/// frames, which may not themselves hold CHAP or CTOC frames.
+///
+/// Frames from tags before ID3v2.4 are upgraded. Those which can't be are added to `unconverted` instead of being
+/// returned.
+fn read_frames(
+	content: &[u8],
+	ix: usize,
+	major_version: u8,
+	tag_unsynchronised: bool,
+	embedded: bool,
+	unconverted: &mut Vec<id3::ID3Frame>,
+) -> Result<(Vec<id3::ID3Frame>, usize), TagError> {
+	let mut frames = Vec::<id3::ID3Frame>::new();
+	let mut cursor = Cursor::at(content, ix);
+
+	// A zero byte starts the padding
+	while cursor.peek().is_some_and(|b| b != 0) {
+		let header_start = cursor.offset();
+		let (id, sz, flags) = if major_version == 2 {
+			// ID3v2.2 frames have a 3 character ID and a 3 byte size, and no flags
+			let v22_id: [u8; 3] = cursor.array()?;
+			if !v22_id.iter().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit()) {
+				return Err(TagError::InvalidFrameId {
+					frame: Some([v22_id[0], v22_id[1], v22_id[2], b' ']),
+					offset: header_start,
+				});
+			}
+			let [a, b, c] = cursor.array()?;
+			let sz = u32::from_be_bytes([0, a, b, c]) as usize;
+			// Frames with no ID3v2.4 equivalent keep their ID followed by a space
+			let id = id3::upgrade_v22_id(&v22_id).unwrap_or([v22_id[0], v22_id[1], v22_id[2], b' ']);
+			(id, sz, [0, 0])
 		} else {
-			id3::from_synchsafe(content[ix..ix + 4].try_into().unwrap()) as usize
+			let id: [u8; 4] = cursor.array()?;
+			if !id.iter().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit()) {
+				return Err(TagError::InvalidFrameId {
+					frame: Some(id),
+					offset: header_start,
+				});
+			}
+			let size_bytes = cursor.array()?;
+			let sz = if major_version <= 3 {
+				u32::from_be_bytes(size_bytes) as usize
+			} else {
+				id3::from_synchsafe(size_bytes) as usize
+			};
+			(id, sz, cursor.array()?)
 		};
-		ix += 4;
-		let flags = &content[ix..ix + 2];
-		if flags[0] != 0 || flags[1] != 0 {
-			panic!("flags != 0: {} {}\ncode: {}", flags[0], flags[1], code);
-		}
-		ix += 2;
 
-		let data: id3::ID3FrameType = match code {
+		let frame_start = cursor.offset();
+		let in_frame = |e: TagError| e.in_frame(id, frame_start);
+		let frame_content = cursor.take(sz).map_err(|e| e.in_frame(id, 0))?;
+		let (flags, frame_content) =
+			handle_frame_format(frame_content, flags, major_version, tag_unsynchronised).map_err(in_frame)?;
+		let frame_content: &[u8] = &frame_content;
+
+		let data: id3::ID3FrameType = match &id {
+			// Encrypted frames can't be parsed
+			_ if flags.encryption.is_some() => id3::ID3FrameType::Raw(frame_content.to_vec()),
 			// Attached Picture
-			"APIC" => id3::ID3FrameType::Picture(handle_pic(&content[ix..ix + sz])),
-			// Comments
-			"COMM" => id3::ID3FrameType::Comment(handle_comm(&content[ix..ix + sz])),
-			// Album/Movie/Show title
-			"TALB" => id3::ID3FrameType::Text(handle_t(&content[ix..ix + sz])),
-			// Content
-			"TCON" => id3::ID3FrameType::Text(handle_t(&content[ix..ix + sz])),
-			// Date
-			"TDAT" => {
-				let day_bytes: &[u8] = content[ix + 1..ix + 1 + 2].try_into().unwrap();
-				let month_bytes: &[u8] = content[ix + 3..ix + 3 + 2].try_into().unwrap();
-				let day = match std::str::from_utf8(day_bytes) {
-					Err(e) => {
-						println!("Could not parse TDAT day: {}: {:?}", e, &content[ix + 1..ix + 1 + 4]);
-						ix += sz;
-						continue;
-					}
-					Ok(x) => x,
-				};
-				let month = match std::str::from_utf8(month_bytes) {
-					Err(e) => {
-						println!("Could not parse TDAT month: {}: {:?}", e, &content[ix + 1..ix + 1 + 4]);
-						ix += sz;
-						continue;
-					}
-					Ok(x) => x,
-				};
-				println!("TDAT: {day} {month}");
-				tdat_day = Some(day);
-				tdat_month = Some(month);
-				if let Some(year) = tyer {
-					code = "TDRC";
-					id3::ID3FrameType::Text(id3::ID3TextFrame {
-						data: year.to_string() + "-" + month + "-" + day,
-						encoding: 0,
-					})
-				} else {
-					// panic!("TYER not found. Required for TDAT.");
-					// Saved and handled in TYER
-					ix += sz;
-					continue;
-				}
+			b"APIC" if major_version == 2 => id3::ID3FrameType::Picture(handle_v22_pic(frame_content).map_err(in_frame)?),
+			b"APIC" => id3::ID3FrameType::Picture(handle_pic(frame_content).map_err(in_frame)?),
+			// Chapters can't be nested, so nested ones are kept unparsed
+			b"CHAP" | b"CTOC" if embedded => id3::ID3FrameType::Raw(frame_content.to_vec()),
+			// Chapter
+			b"CHAP" => {
+				id3::ID3FrameType::Chapter(handle_chap(frame_content, major_version, unconverted).map_err(in_frame)?)
 			}
-			// Recording time
-			"TDRC" => id3::ID3FrameType::Text(handle_t(&content[ix..ix + sz])),
-			// Encoded by
-			"TENC" => id3::ID3FrameType::Text(handle_t(&content[ix..ix + sz])),
-			// Content group description
-			"TIT1" => id3::ID3FrameType::Text(handle_t(&content[ix..ix + sz])),
-			// Title/songname/content description
-			"TIT2" => id3::ID3FrameType::Text(handle_t(&content[ix..ix + sz])),
-			// The length of the audio file in milliseconds, represented as a numeric string.
-			"TLEN" => {
-				let frame = handle_t(&content[ix..ix + sz]);
-				println!("Ignoring TLEN frame: {}", frame.data);
-				ix += sz;
-				continue;
+			// Comments
+			b"COMM" => id3::ID3FrameType::Comment(handle_comm(frame_content).map_err(in_frame)?),
+			// Text information frames
+			_ if id3::is_text_id(&id) => {
+				id3::ID3FrameType::Text(handle_t(frame_content, &id, major_version).map_err(in_frame)?)
 			}
-			// Lead performer(s)/Soloist(s)
-			"TPE1" => id3::ID3FrameType::Text(handle_t(&content[ix..ix + sz])),
-			// Band/orchestra/accompaniment
-			"TPE2" => id3::ID3FrameType::Text(handle_t(&content[ix..ix + sz])),
-			// Track number/Position in set
-			"TRCK" => id3::ID3FrameType::Text(handle_t(&content[ix..ix + sz])),
-			// Album sort order
-			"TSOA" => id3::ID3FrameType::Text(handle_t(&content[ix..ix + sz])),
-			// Software/Hardware and settings used for encodin
-			"TSSE" => id3::ID3FrameType::Text(handle_t(&content[ix..ix + sz])),
 			// User defined text information frame
-			"TXXX" => id3::ID3FrameType::Text(handle_t(&content[ix..ix + sz])),
-			// Year
-			"TYER" => {
-				let year_bytes: &[u8] = content[ix + 1..ix + 1 + 4].try_into().unwrap();
-				let year = match std::str::from_utf8(year_bytes) {
-					Err(e) => {
-						println!("Could not parse TYER year: {}: {:?}", e, &content[ix + 1..ix + 5]);
-						ix += sz;
-						continue;
-					}
-					Ok(x) => x,
-				};
-				tyer = Some(year);
-				if let Some(month) = tdat_month {
-					if let Some(day) = tdat_day {
-						code = "TDRC";
-						id3::ID3FrameType::Text(id3::ID3TextFrame {
-							data: year.to_string() + "-" + month + "-" + day,
-							encoding: 0,
-						})
-					} else {
-						ix += sz;
-						continue;
-					}
-				} else {
-					ix += sz;
-					continue;
-				}
+			b"TXXX" => id3::ID3FrameType::UserText(handle_txxx(frame_content).map_err(in_frame)?),
+			// URL link frames
+			b"WCOM" | b"WCOP" | b"WOAF" | b"WOAR" | b"WOAS" | b"WORS" | b"WPAY" | b"WPUB" => {
+				id3::ID3FrameType::Url(handle_url(frame_content).map_err(in_frame)?)
+			}
+			// Play counter
+			b"PCNT" => match handle_pcnt(frame_content) {
+				Some(f) => id3::ID3FrameType::PlayCounter(f),
+				None => id3::ID3FrameType::Raw(frame_content.to_vec()),
+			},
+			// Popularimeter
+			b"POPM" => match handle_popm(frame_content).map_err(in_frame)? {
+				Some(f) => id3::ID3FrameType::Popularimeter(f),
+				None => id3::ID3FrameType::Raw(frame_content.to_vec()),
+			},
+			// Involved people list (ID3v2.3)
+			b"IPLS" => id3::ID3FrameType::Text(handle_t(frame_content, &id, major_version).map_err(in_frame)?),
+			// Unofficial sort order frames (ID3v2.3)
+			b"XSOA" | b"XSOP" | b"XSOT" => {
+				id3::ID3FrameType::Text(handle_t(frame_content, &id, major_version).map_err(in_frame)?)
 			}
+			// Table of contents
+			b"CTOC" => id3::ID3FrameType::TableOfContents(
+				handle_ctoc(frame_content, major_version, unconverted).map_err(in_frame)?,
+			),
+			// General encapsulated object
+			b"GEOB" => id3::ID3FrameType::GeneralObject(handle_geob(frame_content).map_err(in_frame)?),
+			// Private frame
+			b"PRIV" => id3::ID3FrameType::Private(handle_priv(frame_content).map_err(in_frame)?),
+			// Relative volume adjustment
+			b"RVA2" => id3::ID3FrameType::RelativeVolume(handle_rva2(frame_content).map_err(in_frame)?),
+			// Synchronised lyrics/text
+			b"SYLT" => id3::ID3FrameType::SyncLyrics(handle_sylt(frame_content).map_err(in_frame)?),
+			// Unique file identifier
+			b"UFID" => id3::ID3FrameType::UniqueFileId(handle_ufid(frame_content).map_err(in_frame)?),
+			// User defined URL link frame
+			b"WXXX" => id3::ID3FrameType::UserUrl(handle_wxxx(frame_content).map_err(in_frame)?),
 			// Unsynchronised lyric/text transcription
-			"USLT" => id3::ID3FrameType::Comment(handle_uslt(&content[ix..ix + sz])),
-			_ => panic!("Unhandled tag: {}", code),
+			b"USLT" => id3::ID3FrameType::Comment(handle_uslt(frame_content).map_err(in_frame)?),
+			// Frames that aren't understood yet are kept as-is so they can be written back unchanged
+			_ => id3::ID3FrameType::Raw(frame_content.to_vec()),
 		};
 
-		// new_size += 10 + &data.len();
-
 		let is_empty = match &data {
 			ID3FrameType::Comment(f) => f.text.is_empty() && f.language.is_empty() && f.content_desc.is_empty(),
 			ID3FrameType::Picture(f) => f.data.is_empty() && f.description.is_empty() && f.mime.is_empty(),
-			ID3FrameType::Text(f) => f.data.is_empty(),
+			ID3FrameType::Text(f) => f.values.iter().all(String::is_empty),
+			ID3FrameType::UserText(f) => f.description.is_empty() && f.values.is_empty(),
+			ID3FrameType::Url(f) => f.url.is_empty(),
+			ID3FrameType::UserUrl(f) => f.description.is_empty() && f.url.is_empty(),
+			ID3FrameType::UniqueFileId(f) => f.owner.is_empty() && f.identifier.is_empty(),
+			ID3FrameType::Private(f) => f.owner.is_empty() && f.data.is_empty(),
+			ID3FrameType::Popularimeter(_) | ID3FrameType::PlayCounter(_) => false,
+			ID3FrameType::SyncLyrics(f) => f.lyrics.is_empty(),
+			ID3FrameType::Chapter(_) | ID3FrameType::TableOfContents(_) => false,
+			ID3FrameType::GeneralObject(_) => false,
+			ID3FrameType::RelativeVolume(f) => f.channels.is_empty(),
+			ID3FrameType::Raw(_) => false,
 		};
 
-		if !is_empty {
-			frames.push(id3::ID3Frame {
-				id: code.as_bytes().try_into().unwrap(),
-				// size: sz as u32,
-				flags: flags[0..2].try_into().unwrap(),
-				data,
-			});
+		if is_empty {
+			continue;
+		}
+		// ID3v2.2 text frames with no ID3v2.4 equivalent are kept as TXXX frames described by their old ID. Other
+		// frames with no equivalent can't be written.
+		if id[3] == b' ' {
+			match data {
+				ID3FrameType::Text(f) => frames.push(id3::ID3Frame {
+					id: *b"TXXX",
+					flags,
+					data: ID3FrameType::UserText(id3::ID3UserTextFrame {
+						description: String::from_utf8_lossy(&id[..3]).into_owned(),
+						values: f.values,
+						encoding: f.encoding,
+					}),
+				}),
+				_ => unconverted.push(id3::ID3Frame { id, flags, data }),
+			}
+			continue;
 		}
+		frames.push(id3::ID3Frame { id, flags, data });
+	}
 
-		ix += sz;
+	if major_version <= 3 {
+		let (upgraded, mut not_upgraded) = id3::upgrade_to_v24(frames);
+		frames = upgraded;
+		unconverted.append(&mut not_upgraded);
 	}
 
-	(frames, id3_size)
+	Ok((frames, cursor.offset()))
 }
 
-fn read_to_null(content: &[u8], encoding: u8) -> (String, usize) {
-	match encoding {
-		0 | 3 => {
-			let mut i = 0;
-			while content[i] != 0 {
-				i += 1;
-				if i >= content.len() {
-					break;
-				}
-			}
-			(std::str::from_utf8(&content[0..i]).unwrap().to_string(), i + 1)
+/// Decode a frame's status and format flags, and undo unsynchronisation, grouping, compression and the data length
+/// indicator so that the content can be parsed. Encrypted content is returned without decompression.
+fn handle_frame_format(
+	content: &[u8],
+	flags: [u8; 2],
+	version: u8,
+	tag_unsynchronised: bool,
+) -> Result<(id3::ID3FrameFlags, Cow<'_, [u8]>), TagError> {
+	let mut frame_flags = id3::ID3FrameFlags::default();
+	if version == 2 {
+		return Ok((frame_flags, Cow::Borrowed(content)));
+	}
+	let unsupported = flags[1] & if version == 3 { 0b0001_1111 } else { 0b1011_0000 };
+	if unsupported != 0 {
+		return Err(TagError::UnsupportedFlags {
+			frame: None,
+			offset: 0,
+			flags,
+		});
+	}
+	// Unsynchronisation covers everything after the frame header, so is undone first. The header flag means every
+	// frame is unsynchronised.
+	let data = if version != 3 && (flags[1] & 0b0000_0010 != 0 || tag_unsynchronised) {
+		frame_flags.unsynchronisation = true;
+		Cow::Owned(id3::resynchronise(content))
+	} else {
+		Cow::Borrowed(content)
+	};
+
+	let mut cursor = Cursor::new(&data);
+	if version == 3 {
+		frame_flags.tag_alter_preservation = flags[0] & 0b1000_0000 != 0;
+		frame_flags.file_alter_preservation = flags[0] & 0b0100_0000 != 0;
+		frame_flags.read_only = flags[0] & 0b0010_0000 != 0;
+		frame_flags.compression = flags[1] & 0b1000_0000 != 0;
+		// Compressed frames start with the decompressed size
+		if frame_flags.compression {
+			frame_flags.data_length_indicator = Some(cursor.u32_be()?);
 		}
-		1 => {
-			let mut i = 0;
-			while !(content[i] == 0 && content[i + 1] == 0) {
-				i += 1;
-				if i >= content.len() {
-					break;
-				}
-			}
-			if i == 0 {
-				return (String::new(), i + 2);
-			}
-			if content[0] == 0xFF && content[1] == 0xFE {
-				let uv: Vec<u16> = content[2..i]
-					.chunks_exact(2)
-					.map(|a| u16::from_be_bytes([a[1], a[0]]))
-					.collect();
-				i += 2;
-				(String::from_utf16(&uv).unwrap(), i)
-			} else if content[0] == 0xFE && content[1] == 0xFF {
-				let uv: Vec<u16> = content[2..i]
-					.chunks_exact(2)
-					.map(|a| u16::from_be_bytes([a[0], a[1]]))
-					.collect();
-				i += 2;
-				(String::from_utf16(&uv).unwrap(), i)
-			} else {
-				panic!("Expected FF FE or FE FF, got {:x} {:x}", content[0], content[1]);
-			}
+		if flags[1] & 0b0100_0000 != 0 {
+			frame_flags.encryption = Some(cursor.byte()?);
 		}
-		2 => {
-			let mut i = 0;
-			while !(content[i] == 0 && content[i + 1] == 0) {
-				i += 1;
-				if i >= content.len() {
-					break;
-				}
-			}
-			if i == 0 {
-				return (String::new(), i + 2);
-			}
-			let uv: Vec<u16> = content[0..i]
-				.chunks_exact(2)
-				.map(|a| u16::from_be_bytes([a[1], a[0]]))
-				.collect();
-			i += 2;
-			(String::from_utf16(&uv).unwrap(), i)
+		if flags[1] & 0b0010_0000 != 0 {
+			frame_flags.grouping_identity = Some(cursor.byte()?);
+		}
+	} else {
+		frame_flags.tag_alter_preservation = flags[0] & 0b0100_0000 != 0;
+		frame_flags.file_alter_preservation = flags[0] & 0b0010_0000 != 0;
+		frame_flags.read_only = flags[0] & 0b0001_0000 != 0;
+		frame_flags.compression = flags[1] & 0b0000_1000 != 0;
+		if flags[1] & 0b0100_0000 != 0 {
+			frame_flags.grouping_identity = Some(cursor.byte()?);
+		}
+		if flags[1] & 0b0000_0100 != 0 {
+			frame_flags.encryption = Some(cursor.byte()?);
+		}
+		if flags[1] & 0b0000_0001 != 0 {
+			frame_flags.data_length_indicator = Some(id3::from_synchsafe(cursor.array()?));
 		}
-		_ => panic!("unhandled encoding: {}", encoding),
 	}
+
+	let ix = cursor.offset();
+	let data = match data {
+		Cow::Borrowed(b) => Cow::Borrowed(&b[ix..]),
+		Cow::Owned(v) => Cow::Owned(v[ix..].to_vec()),
+	};
+	if frame_flags.compression && frame_flags.encryption.is_none() {
+		// Limited to the largest possible tag, so a small frame can't expand to fill the memory
+		let mut decompressed = Vec::<u8>::new();
+		let mut decoder = flate2::read::ZlibDecoder::new(&data[..]).take(MAX_TAG_SIZE + 1);
+		if decoder.read_to_end(&mut decompressed).is_err() || decompressed.len() as u64 > MAX_TAG_SIZE {
+			return Err(TagError::InvalidCompressedData {
+				frame: None,
+				offset: ix,
+			});
+		}
+		return Ok((frame_flags, Cow::Owned(decompressed)));
+	}
+	Ok((frame_flags, data))
 }
 
-fn read_as_utf8(content: &[u8], encoding: u8) -> String {
-	match encoding {
-		0 | 3 => std::str::from_utf8(content).unwrap().to_string(),
-		1 => {
-			if content[0] == 0xFF && content[1] == 0xFE {
-				let uv: Vec<u16> = content[2..]
-					.chunks_exact(2)
-					.map(|a| u16::from_be_bytes([a[1], a[0]]))
-					.collect();
-				String::from_utf16(&uv).unwrap()
-			} else if content[0] == 0xFE && content[1] == 0xFF {
-				let uv: Vec<u16> = content[2..]
-					.chunks_exact(2)
-					.map(|a| u16::from_be_bytes([a[0], a[1]]))
-					.collect();
-				String::from_utf16(&uv).unwrap()
-			} else {
-				panic!(
-					"Expected FF FE or FE FF, got {:x} {:x} {}",
-					content[0],
-					content[1],
-					content.len()
-				);
+/// Parse the extended header at the start of `content`. Returns the header and its size in bytes.
+fn handle_extended_header(content: &[u8], version: u8) -> Result<(id3::ID3ExtendedHeader, usize), TagError> {
+	let mut extended_header = id3::ID3ExtendedHeader::default();
+	let mut cursor = Cursor::new(content);
+	let size_bytes = cursor.array()?;
+	if version <= 3 {
+		// The size excludes the size field itself
+		let size = u32::from_be_bytes(size_bytes) as usize;
+		if size != 6 && size != 10 {
+			return Err(TagError::InvalidExtendedHeader { offset: 0 });
+		}
+		let mut data = cursor.limit(size)?;
+		let flags: [u8; 2] = data.array()?;
+		extended_header.padding_size = data.u32_be()?;
+		if flags[0] & 0b1000_0000 != 0 {
+			let crc = data
+				.u32_be()
+				.map_err(|_| TagError::InvalidExtendedHeader { offset: 0 })?;
+			extended_header.crc = Some(crc);
+		}
+		Ok((extended_header, 4 + size))
+	} else {
+		let size = id3::from_synchsafe(size_bytes) as usize;
+		if size < 6 {
+			return Err(TagError::InvalidExtendedHeader { offset: 0 });
+		}
+		let mut data = cursor.limit(size - 4)?;
+		if data.byte()? != 1 {
+			return Err(TagError::InvalidExtendedHeader { offset: 4 });
+		}
+		let flags = data.byte()?;
+		// Each set flag is followed by its data, in the order of the flag bits
+		let mut flag_data = |expected_len: u8| -> Result<&[u8], TagError> {
+			let offset = data.offset();
+			if data.byte()? != expected_len {
+				return Err(TagError::InvalidExtendedHeader { offset });
 			}
+			data.take(expected_len as usize)
+		};
+		if flags & 0b0100_0000 != 0 {
+			flag_data(0)?;
+			extended_header.tag_is_update = true;
+		}
+		if flags & 0b0010_0000 != 0 {
+			let crc: [u8; 5] = flag_data(5)?.try_into().unwrap();
+			// 35 bit synchsafe integer, of which only 32 bits are used
+			let crc = ((crc[0] as u32) << 28) | id3::from_synchsafe([crc[1], crc[2], crc[3], crc[4]]);
+			extended_header.crc = Some(crc);
 		}
-		2 => {
-			let uv: Vec<u16> = content
-				.chunks_exact(2)
-				.map(|a| u16::from_be_bytes([a[1], a[0]]))
-				.collect();
-			String::from_utf16(&uv).unwrap()
+		if flags & 0b0001_0000 != 0 {
+			let restrictions = flag_data(1)?;
+			extended_header.restrictions = Some(id3::ID3TagRestrictions::from_byte(restrictions[0]));
 		}
-		_ => panic!("unhandled encoding: {}", encoding),
+		Ok((extended_header, size))
 	}
 }
 
-fn handle_other_text(content: &[u8]) -> id3::ID3CommentFrame {
-	let mut ix = 0;
-	let encoding: u8 = content[ix];
-	ix += 1;
-
-	// let lang = std::str::from_utf8(&content[ix..ix + 3]).unwrap();
-	ix += 3;
-
-	let (content_descriptor, last) = read_to_null(&content[ix..], encoding);
-	ix += last;
-	let data = read_as_utf8(&content[ix..], encoding);
-
-	// println!("code:   {}", code);
-	// println!("name:   {}", name);
-	// println!("length: {}", content.len());
-	// println!("encode: {}", encoding);
-	// println!("lang:   {}", lang);
-	// println!("c_desc: {}", content_descriptor);
-	// println!("text:   {}", data);
-
-	id3::ID3CommentFrame {
-		language: [content[1], content[2], content[3]],
-		content_desc: content_descriptor,
-		text: data,
-		// text: "12345是".to_string(),
+fn handle_other_text(content: &[u8]) -> Result<id3::ID3CommentFrame, TagError> {
+	let mut cursor = Cursor::new(content);
+	let encoding = cursor.byte()?;
+	let language = cursor.array()?;
+	let content_desc = cursor.string(encoding)?;
+	let text = cursor.text(encoding)?;
+
+	Ok(id3::ID3CommentFrame {
+		language,
+		content_desc,
+		text,
 		encoding,
-	}
+	})
 }
 
-fn handle_uslt(content: &[u8]) -> id3::ID3CommentFrame {
+fn handle_uslt(content: &[u8]) -> Result<id3::ID3CommentFrame, TagError> {
 	handle_other_text(content)
 }
 
-fn handle_comm(content: &[u8]) -> id3::ID3CommentFrame {
+fn handle_sylt(content: &[u8]) -> Result<id3::ID3SyncLyricsFrame, TagError> {
+	let mut cursor = Cursor::new(content);
+	let encoding = cursor.byte()?;
+	let language = cursor.array()?;
+	let timestamp_format = cursor.byte()?;
+	let content_type = cursor.byte()?;
+	let description = cursor.string(encoding)?;
+
+	let mut lyrics = Vec::new();
+	while !cursor.is_empty() {
+		let text = cursor.string(encoding)?;
+		let timestamp = cursor.u32_be()?;
+		lyrics.push((text, timestamp));
+	}
+
+	Ok(id3::ID3SyncLyricsFrame {
+		language,
+		timestamp_format,
+		content_type,
+		description,
+		lyrics,
+		encoding,
+	})
+}
+
+fn handle_chap(
+	content: &[u8],
+	major_version: u8,
+	unconverted: &mut Vec<id3::ID3Frame>,
+) -> Result<id3::ID3ChapterFrame, TagError> {
+	let mut cursor = Cursor::new(content);
+	let element_id = cursor.string(0)?;
+	let start_time = cursor.u32_be()?;
+	let end_time = cursor.u32_be()?;
+	let start_offset = cursor.u32_be()?;
+	let end_offset = cursor.u32_be()?;
+	// Unsynchronisation was undone for the whole frame, including the embedded frames
+	let (frames, _) = read_frames(content, cursor.offset(), major_version, false, true, unconverted)?;
+
+	Ok(id3::ID3ChapterFrame {
+		element_id,
+		start_time,
+		end_time,
+		start_offset,
+		end_offset,
+		frames,
+	})
+}
+
+fn handle_ctoc(
+	content: &[u8],
+	major_version: u8,
+	unconverted: &mut Vec<id3::ID3Frame>,
+) -> Result<id3::ID3TableOfContentsFrame, TagError> {
+	let mut cursor = Cursor::new(content);
+	let element_id = cursor.string(0)?;
+	let flags = cursor.byte()?;
+	let child_count = cursor.byte()?;
+	let mut children = Vec::with_capacity(child_count as usize);
+	for _ in 0..child_count {
+		children.push(cursor.string(0)?);
+	}
+	// Unsynchronisation was undone for the whole frame, including the embedded frames
+	let (frames, _) = read_frames(content, cursor.offset(), major_version, false, true, unconverted)?;
+
+	Ok(id3::ID3TableOfContentsFrame {
+		element_id,
+		top_level: flags & 0b10 != 0,
+		ordered: flags & 0b01 != 0,
+		children,
+		frames,
+	})
+}
+
+fn handle_comm(content: &[u8]) -> Result<id3::ID3CommentFrame, TagError> {
 	handle_other_text(content)
 }
 
-fn handle_t(content: &[u8]) -> id3::ID3TextFrame {
-	let mut ix = 0;
-	let len = content.len();
-	let encoding = content[ix];
-	ix += 1;
-	let text: String = match encoding {
-		0 | 3 => std::str::from_utf8(&content[ix..ix + len - 1]).unwrap().to_string(),
-		1 => {
-			if content[ix] == 0xFF && content[ix + 1] == 0xFE {
-				let uv: Vec<u16> = content[ix + 2..(ix + 2 + len - 6)]
-					.chunks_exact(2)
-					.map(|a| u16::from_be_bytes([a[1], a[0]]))
-					.collect();
-				String::from_utf16(&uv).unwrap()
-			} else if content[ix] == 0xFE && content[ix + 1] == 0xFF {
-				let uv: Vec<u16> = content[ix + 2..(ix + 2 + len - 6)]
-					.chunks_exact(2)
-					.map(|a| u16::from_be_bytes([a[0], a[1]]))
-					.collect();
-				String::from_utf16(&uv).unwrap()
-			} else {
-				panic!("Expected FF FE or FE FF, got {:x} {:x}", content[ix], content[ix + 1]);
-			}
-		}
-		2 => {
-			let uv: Vec<u16> = content[ix + 2..(ix + 2 + len - 6)]
-				.chunks_exact(2)
-				.map(|a| u16::from_be_bytes([a[1], a[0]]))
-				.collect();
-			String::from_utf16(&uv).unwrap()
+fn handle_t(content: &[u8], id: &[u8; 4], major_version: u8) -> Result<id3::ID3TextFrame, TagError> {
+	let mut cursor = Cursor::new(content);
+	let encoding = cursor.byte()?;
+	let mut values = cursor.values(encoding)?;
+	// Some writers pad the text with extra terminators
+	while values.last().is_some_and(String::is_empty) {
+		values.pop();
+	}
+	// Before ID3v2.4 a frame holds one string. A few frames list several people separated by "/".
+	if major_version < 4 && id3::is_slash_separated(id) {
+		values = values.iter().flat_map(|v| v.split('/')).map(String::from).collect();
+	}
+
+	Ok(id3::ID3TextFrame { values, encoding })
+}
+
+fn handle_txxx(content: &[u8]) -> Result<id3::ID3UserTextFrame, TagError> {
+	let mut cursor = Cursor::new(content);
+	let encoding = cursor.byte()?;
+	let description = cursor.string(encoding)?;
+	let values = cursor.values(encoding)?;
+
+	Ok(id3::ID3UserTextFrame {
+		description,
+		values,
+		encoding,
+	})
+}
+
+fn handle_url(content: &[u8]) -> Result<id3::ID3UrlFrame, TagError> {
+	// Not terminated, but some writers add one anyway
+	let url = Cursor::new(content).string(0)?;
+	Ok(id3::ID3UrlFrame { url })
+}
+
+fn handle_wxxx(content: &[u8]) -> Result<id3::ID3UserUrlFrame, TagError> {
+	let mut cursor = Cursor::new(content);
+	let encoding = cursor.byte()?;
+	let description = cursor.string(encoding)?;
+	// The URL is always ISO-8859-1
+	let url = cursor.string(0)?;
+
+	Ok(id3::ID3UserUrlFrame {
+		description,
+		url,
+		encoding,
+	})
+}
+
+fn handle_ufid(content: &[u8]) -> Result<id3::ID3UniqueFileIdFrame, TagError> {
+	let mut cursor = Cursor::new(content);
+	let owner = cursor.string(0)?;
+	Ok(id3::ID3UniqueFileIdFrame {
+		owner,
+		identifier: cursor.rest().to_vec(),
+	})
+}
+
+fn handle_priv(content: &[u8]) -> Result<id3::ID3PrivateFrame, TagError> {
+	let mut cursor = Cursor::new(content);
+	let owner = cursor.string(0)?;
+	Ok(id3::ID3PrivateFrame {
+		owner,
+		data: cursor.rest().to_vec(),
+	})
+}
+
+/// Read a big-endian counter of any length. Returns None if it doesn't fit in 64 bits.
+fn read_counter(content: &[u8]) -> Option<u64> {
+	let start = content.iter().take_while(|b| **b == 0).count();
+	if content.len() - start > 8 {
+		return None;
+	}
+	Some(content[start..].iter().fold(0, |acc, b| (acc << 8) | *b as u64))
+}
+
+/// Returns None for counters too large to represent, so the frame can be kept as-is.
+fn handle_pcnt(content: &[u8]) -> Option<id3::ID3PlayCounterFrame> {
+	read_counter(content).map(|counter| id3::ID3PlayCounterFrame { counter })
+}
+
+/// Returns None for counters too large to represent, so the frame can be kept as-is.
+fn handle_popm(content: &[u8]) -> Result<Option<id3::ID3PopularimeterFrame>, TagError> {
+	let mut cursor = Cursor::new(content);
+	let email = cursor.string(0)?;
+	let rating = cursor.byte()?;
+	let counter = if cursor.is_empty() {
+		None
+	} else {
+		match read_counter(cursor.rest()) {
+			Some(x) => Some(x),
+			None => return Ok(None),
 		}
-		_ => panic!("Forbidden encoding: {}", encoding),
 	};
 
-	// println!("code:   {}", code);
-	// println!("name:   {}", name);
-	// println!("length: {}", len);
-	// println!("encode: {}", encoding);
-	// println!("text:   {}", text);
-
-	id3::ID3TextFrame { data: text, encoding }
-}
-
-fn handle_pic(content: &[u8]) -> id3::ID3PictureFrame {
-	let encoding = content[0];
-	let (mime, last) = read_to_null(&content[1..], encoding);
-	let pic_type = content[last + 1];
-	let (description, nlast) = read_to_null(&content[last + 1 + 1..], encoding);
-	// println!("code:   {}", code);
-	// println!("name:   {}", name);
-	// println!("length: {}", content.len());
-	// println!("encode: {}", encoding);
-	// println!("mime:   {}", mime);
-	// println!("pic_typ:{:X}", pic_type);
-	// println!("desc:   {}", description);
-	// println!("size:   {}", content.len() - (nlast + last + 2));
-
-	id3::ID3PictureFrame {
+	Ok(Some(id3::ID3PopularimeterFrame { email, rating, counter }))
+}
+
+fn handle_geob(content: &[u8]) -> Result<id3::ID3GeneralObjectFrame, TagError> {
+	let mut cursor = Cursor::new(content);
+	let encoding = cursor.byte()?;
+	// The MIME type is always ISO-8859-1
+	let mime = cursor.string(0)?;
+	let filename_bom = read_bom(&cursor, encoding);
+	let filename = cursor.string(encoding)?;
+	let description_bom = read_bom(&cursor, encoding);
+	let description = cursor.string(encoding)?;
+
+	Ok(id3::ID3GeneralObjectFrame {
 		mime,
-		pic_type,
+		filename,
 		description,
-		data: content[last + nlast + 2..].to_vec(),
+		data: cursor.rest().to_vec(),
+		encoding,
+		filename_bom,
+		description_bom,
+	})
+}
+
+/// The byte order mark of the UTF-16 string at the cursor, without moving past it.
+fn read_bom(cursor: &Cursor, encoding: u8) -> id3::ByteOrderMark {
+	if encoding != 1 {
+		return id3::ByteOrderMark::default();
+	}
+	match cursor.clone().array() {
+		Ok([0xFE, 0xFF]) => id3::ByteOrderMark::BigEndian,
+		Ok([0xFF, 0xFE]) => id3::ByteOrderMark::LittleEndian,
+		_ => id3::ByteOrderMark::Omitted,
 	}
 }
 
+fn handle_rva2(content: &[u8]) -> Result<id3::ID3RelativeVolumeFrame, TagError> {
+	let mut cursor = Cursor::new(content);
+	let identification = cursor.string(0)?;
+	let mut channels = Vec::new();
+	while !cursor.is_empty() {
+		let channel_type = cursor.byte()?;
+		let adjustment = i16::from_be_bytes(cursor.array()?);
+		let peak_bits = cursor.byte()?;
+		let peak = cursor.take((peak_bits as usize).div_ceil(8))?.to_vec();
+		channels.push(id3::ID3ChannelVolume {
+			channel_type,
+			adjustment,
+			peak_bits,
+			peak,
+		});
+	}
+
+	Ok(id3::ID3RelativeVolumeFrame {
+		identification,
+		channels,
+	})
+}
+
+fn handle_pic(content: &[u8]) -> Result<id3::ID3PictureFrame, TagError> {
+	let mut cursor = Cursor::new(content);
+	let encoding = cursor.byte()?;
+	// The MIME type is always ISO-8859-1
+	let mime = cursor.string(0)?;
+	let pic_type = cursor.byte()?;
+	let description = cursor.string(encoding)?;
+
+	Ok(id3::ID3PictureFrame {
+		mime,
+		pic_type,
+		description,
+		data: cursor.rest().to_vec(),
+	})
+}
+
+/// ID3v2.2 PIC frames have a 3 character image format in place of the MIME type.
+fn handle_v22_pic(content: &[u8]) -> Result<id3::ID3PictureFrame, TagError> {
+	let mut cursor = Cursor::new(content);
+	let encoding = cursor.byte()?;
+	let image_format: [u8; 3] = cursor.array()?;
+	let mime = match &image_format {
+		b"JPG" => String::from("image/jpeg"),
+		b"-->" => String::from("-->"),
+		_ => String::from("image/") + &String::from_utf8_lossy(&image_format).to_lowercase(),
+	};
+	let pic_type = cursor.byte()?;
+	let description = cursor.string(encoding)?;
+
+	Ok(id3::ID3PictureFrame {
+		mime,
+		pic_type,
+		description,
+		data: cursor.rest().to_vec(),
+	})
+}
+
 pub fn parse_mp4_frames(content: &[u8]) -> Vec<mp4::FileAtom> {
 	let mut ret = Vec::new();
 	let mut ix = 0;
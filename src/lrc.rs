@@ -0,0 +1,61 @@
+/// Parse LRC text into (text, milliseconds) pairs sorted by time, for use in a SYLT frame.
+///
+/// Each LRC line starts with one or more `[mm:ss.xx]` time tags followed by the text sung from that time. Tags such
+/// as `[ar:Artist]` which aren't times are ignored.
+pub fn from_lrc(lrc: &str) -> Vec<(String, u32)> {
+	let mut ret = Vec::new();
+	for line in lrc.lines() {
+		let mut rest = line.trim();
+		let mut times = Vec::new();
+		while let Some(tag) = rest.strip_prefix('[') {
+			let end = match tag.find(']') {
+				Some(x) => x,
+				None => break,
+			};
+			match parse_timestamp(&tag[..end]) {
+				Some(ms) => times.push(ms),
+				// Metadata tag
+				None => break,
+			}
+			rest = &tag[end + 1..];
+		}
+		for ms in times {
+			ret.push((rest.to_string(), ms));
+		}
+	}
+	ret.sort_by_key(|(_, ms)| *ms);
+	ret
+}
+
+/// Format (text, milliseconds) pairs as LRC text.
+pub fn to_lrc(lyrics: &[(String, u32)]) -> String {
+	let mut ret = String::new();
+	for (text, ms) in lyrics {
+		ret += &format_timestamp(*ms);
+		// SYLT text often starts with a newline to mark the start of a line
+		ret += text.trim_start_matches('\n');
+		ret += "\n";
+	}
+	ret
+}
+
+/// Parse "mm:ss", "mm:ss.xx" or "mm:ss.xxx" into milliseconds.
+fn parse_timestamp(tag: &str) -> Option<u32> {
+	let (minutes, seconds) = tag.split_once(':')?;
+	let (seconds, fraction) = seconds.split_once('.').unwrap_or((seconds, "0"));
+	if fraction.is_empty() || fraction.len() > 3 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+		return None;
+	}
+	let minutes: u32 = minutes.parse().ok()?;
+	let seconds: u32 = seconds.parse().ok()?;
+	if seconds >= 60 {
+		return None;
+	}
+	// Scale hundredths or tenths of a second up to milliseconds
+	let fraction: u32 = fraction.parse::<u32>().ok()? * 10u32.pow(3 - fraction.len() as u32);
+	minutes.checked_mul(60_000)?.checked_add(seconds * 1000 + fraction)
+}
+
+fn format_timestamp(ms: u32) -> String {
+	format!("[{:02}:{:02}.{:02}]", ms / 60_000, ms / 1000 % 60, ms % 1000 / 10)
+}
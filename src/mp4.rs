@@ -1,6 +1,7 @@
 use core::{convert::TryInto, str};
 
 use super::itunes;
+use crate::replaygain::ReplayGain;
 
 fn spacer(depth: u16) -> String {
 	let mut ret = Vec::<u8>::new();
@@ -1574,6 +1575,10 @@ impl ItemListItem {
 			ret.extend_from_slice(&[0, 0, 0]); // flags
 			ret.extend_from_slice(&[0, 0, 0, 0]); // reserved
 			ret.extend_from_slice(&num.to_be_bytes());
+		} else if let ItunesValue::Byte(num) = &self.value {
+			ret.extend_from_slice(&[0, 0, 0x15]); // flags
+			ret.extend_from_slice(&[0, 0, 0, 0]); // reserved
+			ret.push(*num);
 		} else {
 			panic!("Can't handle non-text");
 		}
@@ -1605,26 +1610,27 @@ impl ItunesInfo {
 		let mut ret = self.size().to_be_bytes().to_vec();
 		ret.extend_from_slice(b"----");
 
-		let mean_size = 4 + 4 + 1 + 3 + self.mean.len();
+		let mean_size = 4 + 4 + 1 + 3 + self.mean.len() as u32;
 		ret.extend_from_slice(&mean_size.to_be_bytes());
 		ret.extend_from_slice(b"mean");
-		ret.push(0);
-		ret.extend_from_slice(&[0, 0, 1]);
+		ret.push(0); // version
+		ret.extend_from_slice(&[0, 0, 0]); // flags
 		ret.extend_from_slice(self.mean.as_bytes());
 
-		let name_size = 4 + 4 + 1 + 3 + self.name.len();
+		let name_size = 4 + 4 + 1 + 3 + self.name.len() as u32;
 		ret.extend_from_slice(&name_size.to_be_bytes());
 		ret.extend_from_slice(b"name");
-		ret.push(0);
-		ret.extend_from_slice(&[0, 0, 1]);
+		ret.push(0); // version
+		ret.extend_from_slice(&[0, 0, 0]); // flags
 		ret.extend_from_slice(self.name.as_bytes());
 
-		let data_size = 4 + 4 + 1 + 3 + self.data.size();
+		let data_size = 4 + 4 + 1 + 3 + 4 + self.data.size();
 		ret.extend_from_slice(&data_size.to_be_bytes());
 		ret.extend_from_slice(b"data");
-		ret.push(0);
+		ret.push(0); // version
 		if let ItunesValue::Text(text) = &self.data {
-			ret.extend_from_slice(&[0, 0, 1]);
+			ret.extend_from_slice(&[0, 0, 1]); // flags
+			ret.extend_from_slice(&[0, 0, 0, 0]); // reserved
 			ret.extend_from_slice(text.as_bytes());
 		} else {
 			panic!("Can't handle non-text");
@@ -1637,11 +1643,14 @@ impl ItunesInfo {
 enum ItunesValue {
 	Binary(u32),
 	Text(String),
+	/// Single byte integer (data type 0x15), such as rtng
+	Byte(u8),
 }
 impl ItunesValue {
 	fn size(&self) -> u32 {
 		match self {
 			Self::Binary(_) => 4,
+			Self::Byte(_) => 1,
 			Self::Text(x) => x.len() as u32,
 		}
 	}
@@ -1681,6 +1690,8 @@ pub struct ItemListConfig {
 	pub genre: Option<String>, // Genre
 	pub record_date: Option<String>,
 	pub comment: Option<String>,
+	pub rating: Option<u8>,
+	pub replay_gain: ReplayGain,
 	// pub combine_comments: bool,
 	// pub pictures: Vec<PictureArg>,
 	// pub remove: HashSet<String>,
@@ -1780,6 +1791,14 @@ impl ItemList {
 						b"atID" | b"cmID" | b"cnID" | b"geID" | b"sfID" => ItunesValue::Binary(u32::from_be_bytes(
 							data[data_ix + 16..data_ix + data_sz as usize].try_into().unwrap(),
 						)),
+						// A rating with no value is skipped
+						b"rtng" => match data.get(data_ix + 16).filter(|_| data_sz > 16) {
+							Some(rating) => ItunesValue::Byte(*rating),
+							None => {
+								idx += sz;
+								continue;
+							}
+						},
 						_ => ItunesValue::Binary(u8::from_be_bytes(
 							data[data_ix + 16..data_ix + data_sz as usize].try_into().unwrap(),
 						) as u32),
@@ -1808,6 +1827,7 @@ impl ItemList {
 					ret += &spacer(depth + 1);
 					ret += &match &ili.value {
 						ItunesValue::Binary(x) => format!("{}: {},\n", String::from_utf8_lossy(&ili.tag_id), x),
+						ItunesValue::Byte(x) => format!("{}: {},\n", String::from_utf8_lossy(&ili.tag_id), x),
 						ItunesValue::Text(x) => format!("{}: {},\n", String::from_utf8_lossy(&ili.tag_id), x),
 					};
 				}
@@ -1815,6 +1835,7 @@ impl ItemList {
 					ret += &spacer(depth + 1);
 					ret += &match &info.data {
 						ItunesValue::Binary(x) => format!("{}: {} : {},\n", info.name, x, info.mean),
+						ItunesValue::Byte(x) => format!("{}: {} : {},\n", info.name, x, info.mean),
 						ItunesValue::Text(x) => format!("{}: {} : {},\n", info.name, x, info.mean),
 					};
 				}
@@ -1823,6 +1844,18 @@ impl ItemList {
 		ret += &(spacer(depth) + "]");
 		ret
 	}
+	/// The text of the freeform ("----") item with the given mean and name. Names are compared case-insensitively as
+	/// writers differ.
+	pub fn freeform_text(&self, mean: &str, name: &str) -> Option<&str> {
+		self.items.iter().find_map(|item| match item {
+			ItemListType::ItunesInfo(ItunesInfo {
+				mean: m,
+				name: n,
+				data: ItunesValue::Text(text),
+			}) if m == mean && n.eq_ignore_ascii_case(name) => Some(text.as_str()),
+			_ => None,
+		})
+	}
 	pub fn apply_config(&self, cfg: ItemListConfig) -> Self {
 		println!("apply_config");
 		let mut items = Vec::new();
@@ -1880,6 +1913,24 @@ impl ItemList {
 				value: ItunesValue::Text(comment),
 			}));
 		}
+		if let Some(rating) = cfg.rating {
+			items.push(ItemListType::Item(ItemListItem {
+				tag_id: *b"rtng",
+				value: ItunesValue::Byte(rating),
+			}));
+		}
+		let replace_replay_gain = !cfg.replay_gain.is_empty();
+		if replace_replay_gain {
+			// Values which aren't being set are kept
+			let replay_gain = cfg.replay_gain.or(ReplayGain::from_item_list(self));
+			for (name, value) in replay_gain.text_values() {
+				items.push(ItemListType::ItunesInfo(ItunesInfo {
+					mean: String::from("com.apple.iTunes"),
+					name: name.to_lowercase(),
+					data: ItunesValue::Text(value),
+				}));
+			}
+		}
 		for item in &self.items {
 			match item {
 				ItemListType::Item(item_item) => {
@@ -1892,10 +1943,12 @@ impl ItemList {
 					}
 				}
 				ItemListType::ItunesInfo(itune) => {
-					if items.iter().any(|x| match x {
-						ItemListType::ItunesInfo(i) => itune.name == i.name,
-						_ => false,
-					}) {
+					let is_replay_gain = itune.name.to_lowercase().starts_with("replaygain_");
+					if (replace_replay_gain && is_replay_gain)
+						|| items.iter().any(|x| match x {
+							ItemListType::ItunesInfo(i) => itune.name.eq_ignore_ascii_case(&i.name),
+							_ => false,
+						}) {
 						println!("Skipping {}", itune.name);
 						continue;
 					}
@@ -1947,6 +2000,30 @@ impl FileAtom {
 	}
 }
 
+/// Find the iTunes item list (moov.udta.meta.ilst, or moov.meta.ilst) among parsed top level atoms.
+pub fn find_item_list(atoms: &[FileAtom]) -> Option<&ItemList> {
+	let metas = atoms
+		.iter()
+		.filter_map(|atom| match atom {
+			FileAtom::Movie(movie) => Some(movie),
+			_ => None,
+		})
+		.flat_map(|movie| &movie.children)
+		.flat_map(|child| match child {
+			MovieAtom::UserData(udta) => udta
+				.children
+				.iter()
+				.map(|UserDataAtom::Meta(meta)| meta)
+				.collect::<Vec<_>>(),
+			MovieAtom::Meta(meta) => vec![meta],
+			_ => Vec::new(),
+		});
+	metas.flat_map(|meta| &meta.other_boxes).find_map(|atom| match atom {
+		MetaAtom::ItemList(ilst) => Some(ilst),
+		_ => None,
+	})
+}
+
 pub enum MovieAtom {
 	MovieHeader(MovieHeaderBox),
 	Track(TrackBox),
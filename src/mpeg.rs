@@ -0,0 +1,333 @@
+use core::convert::TryInto;
+use std::io::{Read, Seek, SeekFrom};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MpegVersion {
+	V1,
+	V2,
+	V25,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChannelMode {
+	Stereo,
+	JointStereo,
+	DualChannel,
+	Mono,
+}
+
+/// The 4 byte header at the start of each MPEG audio frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameHeader {
+	pub version: MpegVersion,
+	/// 1, 2 or 3
+	pub layer: u8,
+	/// A 16 bit CRC follows the header
+	pub protected: bool,
+	/// kbit/s
+	pub bitrate: u32,
+	/// Hz
+	pub sample_rate: u32,
+	pub padding: bool,
+	pub channel_mode: ChannelMode,
+}
+
+// Indexed by the 4 bit bitrate field, 0 is "free format" and 15 is invalid
+const BITRATES_V1: [[u32; 15]; 3] = [
+	[0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448],
+	[0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384],
+	[0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320],
+];
+const BITRATES_V2: [[u32; 15]; 3] = [
+	[0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256],
+	[0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+	[0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+];
+
+impl FrameHeader {
+	/// Decode a frame header. Free format frames, which have no bitrate in the header, are not supported.
+	pub fn parse(b: [u8; 4]) -> Option<FrameHeader> {
+		// 11 bits of frame sync
+		if b[0] != 0xFF || b[1] & 0xE0 != 0xE0 {
+			return None;
+		}
+		let version = match (b[1] >> 3) & 0b11 {
+			0b00 => MpegVersion::V25,
+			0b10 => MpegVersion::V2,
+			0b11 => MpegVersion::V1,
+			_ => return None,
+		};
+		let layer = match (b[1] >> 1) & 0b11 {
+			0b01 => 3,
+			0b10 => 2,
+			0b11 => 1,
+			_ => return None,
+		};
+		let bitrate_ix = (b[2] >> 4) as usize;
+		let bitrate = match version {
+			MpegVersion::V1 => BITRATES_V1,
+			_ => BITRATES_V2,
+		}[layer as usize - 1]
+			.get(bitrate_ix)
+			.copied()
+			.filter(|x| *x != 0)?;
+		let sample_rate = match ((b[2] >> 2) & 0b11, version) {
+			(0b11, _) => return None,
+			(ix, MpegVersion::V1) => [44100, 48000, 32000][ix as usize],
+			(ix, MpegVersion::V2) => [22050, 24000, 16000][ix as usize],
+			(ix, MpegVersion::V25) => [11025, 12000, 8000][ix as usize],
+		};
+		let channel_mode = match b[3] >> 6 {
+			0b00 => ChannelMode::Stereo,
+			0b01 => ChannelMode::JointStereo,
+			0b10 => ChannelMode::DualChannel,
+			_ => ChannelMode::Mono,
+		};
+		Some(FrameHeader {
+			version,
+			layer,
+			protected: b[1] & 1 == 0,
+			bitrate,
+			sample_rate,
+			padding: b[2] & 0b10 != 0,
+			channel_mode,
+		})
+	}
+
+	pub fn samples_per_frame(&self) -> u32 {
+		match (self.layer, self.version) {
+			(1, _) => 384,
+			(2, _) | (3, MpegVersion::V1) => 1152,
+			_ => 576,
+		}
+	}
+
+	/// The size of the frame in bytes, including the header.
+	pub fn frame_len(&self) -> usize {
+		if self.layer == 1 {
+			((12 * self.bitrate * 1000 / self.sample_rate + self.padding as u32) * 4) as usize
+		} else {
+			(self.samples_per_frame() / 8 * self.bitrate * 1000 / self.sample_rate + self.padding as u32) as usize
+		}
+	}
+
+	/// The size of the Layer III side information which follows the header.
+	fn side_info_len(&self) -> usize {
+		match (self.version, self.channel_mode) {
+			(MpegVersion::V1, ChannelMode::Mono) => 17,
+			(MpegVersion::V1, _) => 32,
+			(_, ChannelMode::Mono) => 9,
+			_ => 17,
+		}
+	}
+
+	/// Whether `other` could be a following frame of the same stream.
+	fn matches(&self, other: &FrameHeader) -> bool {
+		self.version == other.version && self.layer == other.layer && self.sample_rate == other.sample_rate
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VbrKind {
+	/// Xing header of a VBR file
+	Xing,
+	/// Xing header of a CBR file
+	Info,
+	/// Fraunhofer VBRI header
+	Vbri,
+}
+
+/// The header some encoders write in place of the audio of the first frame, describing the whole stream.
+#[derive(Clone, Debug)]
+pub struct VbrHeader {
+	pub kind: VbrKind,
+	/// Number of audio frames, not counting the frame holding this header
+	pub frames: Option<u32>,
+	/// Number of bytes of audio, including the frame holding this header
+	pub bytes: Option<u32>,
+	/// For Xing, the position at each percent of the duration as a fraction of `bytes` out of 256. For VBRI, the size
+	/// of each section of the stream in bytes.
+	pub toc: Vec<u32>,
+	pub quality: Option<u32>,
+	/// Such as "LAME3.100", from the LAME extension to the Xing header
+	pub encoder: Option<String>,
+	/// Samples added by the encoder at the start, from the LAME extension
+	pub encoder_delay: u16,
+	/// Samples added by the encoder at the end, from the LAME extension
+	pub encoder_padding: u16,
+}
+
+impl VbrHeader {
+	/// Read the Xing or VBRI header in `frame`, the first frame of a stream.
+	pub fn parse(header: &FrameHeader, frame: &[u8]) -> Option<VbrHeader> {
+		if header.layer != 3 {
+			return None;
+		}
+		let xing_start = 4 + header.side_info_len();
+		if let Some(xing) = frame.get(xing_start..) {
+			if xing.starts_with(b"Xing") || xing.starts_with(b"Info") {
+				return parse_xing(xing);
+			}
+		}
+		// VBRI is always 32 bytes after the header
+		frame.get(36..).filter(|x| x.starts_with(b"VBRI")).and_then(parse_vbri)
+	}
+}
+
+fn be_u32(data: &[u8], ix: usize) -> Option<u32> {
+	Some(u32::from_be_bytes(data.get(ix..ix + 4)?.try_into().unwrap()))
+}
+
+fn be_u16(data: &[u8], ix: usize) -> Option<u16> {
+	Some(u16::from_be_bytes(data.get(ix..ix + 2)?.try_into().unwrap()))
+}
+
+fn parse_xing(data: &[u8]) -> Option<VbrHeader> {
+	let flags = be_u32(data, 4)?;
+	let mut ix = 8;
+	let mut field = |flag: u32, len: usize| {
+		if flags & flag == 0 {
+			return None;
+		}
+		let start = ix;
+		ix += len;
+		data.get(start..start + len)
+	};
+	let frames = field(0x1, 4).map(|x| be_u32(x, 0).unwrap());
+	let bytes = field(0x2, 4).map(|x| be_u32(x, 0).unwrap());
+	let toc = field(0x4, 100)
+		.map(|x| x.iter().map(|b| *b as u32).collect())
+		.unwrap_or_default();
+	let quality = field(0x8, 4).map(|x| be_u32(x, 0).unwrap());
+
+	let mut ret = VbrHeader {
+		kind: if data.starts_with(b"Xing") {
+			VbrKind::Xing
+		} else {
+			VbrKind::Info
+		},
+		frames,
+		bytes,
+		toc,
+		quality,
+		encoder: None,
+		encoder_delay: 0,
+		encoder_padding: 0,
+	};
+	// The LAME extension: a 9 character encoder version, then the delay and padding as two 12 bit numbers 21 bytes in
+	if let Some(lame) = data.get(ix..ix + 24) {
+		if lame[0..4].iter().all(|b| b.is_ascii_alphanumeric()) {
+			ret.encoder = Some(
+				String::from_utf8_lossy(&lame[0..9])
+					.trim_end_matches(['\0', ' '])
+					.to_string(),
+			);
+			ret.encoder_delay = ((lame[21] as u16) << 4) | (lame[22] as u16 >> 4);
+			ret.encoder_padding = (((lame[22] & 0x0F) as u16) << 8) | lame[23] as u16;
+		}
+	}
+	Some(ret)
+}
+
+fn parse_vbri(data: &[u8]) -> Option<VbrHeader> {
+	let bytes = be_u32(data, 10)?;
+	let frames = be_u32(data, 14)?;
+	let entries = be_u16(data, 18)? as usize;
+	let scale = be_u16(data, 20)? as u32;
+	let entry_size = be_u16(data, 22)? as usize;
+	let mut toc = Vec::with_capacity(entries);
+	for i in 0..entries {
+		let start = 26 + i * entry_size;
+		let entry = data.get(start..start + entry_size)?;
+		let value = entry.iter().fold(0u32, |acc, b| (acc << 8) | *b as u32);
+		toc.push(value.saturating_mul(scale));
+	}
+	Some(VbrHeader {
+		kind: VbrKind::Vbri,
+		frames: Some(frames),
+		bytes: Some(bytes),
+		toc,
+		quality: be_u16(data, 8).map(u32::from),
+		encoder: None,
+		encoder_delay: 0,
+		encoder_padding: 0,
+	})
+}
+
+/// The properties of an MPEG audio stream, from its first frame.
+#[derive(Clone, Debug)]
+pub struct AudioProperties {
+	pub header: FrameHeader,
+	/// Position of the first frame
+	pub offset: u64,
+	/// Size of the stream from the first frame, excluding any tags after it
+	pub len: u64,
+	pub vbr: Option<VbrHeader>,
+}
+
+/// How far past the start to look for the first frame, to skip junk such as a misplaced tag
+const SEARCH_LEN: u64 = 64 * 1024;
+
+impl AudioProperties {
+	/// Find the first frame of the audio between `start` and `end` of `reader`, such as between the ID3v2 tag and any
+	/// tags at the end of the file. A frame is only accepted if it is followed by another matching frame, or by the
+	/// end of the audio.
+	pub fn read_from<R: Read + Seek>(reader: &mut R, start: u64, end: u64) -> std::io::Result<Option<AudioProperties>> {
+		// Enough to hold the largest frame after the last place a frame could start
+		let read_len = end.saturating_sub(start).min(SEARCH_LEN + 8192);
+		let mut data = vec![0u8; read_len as usize];
+		reader.seek(SeekFrom::Start(start))?;
+		reader.read_exact(&mut data)?;
+
+		let search_end = data.len().saturating_sub(4).min(SEARCH_LEN as usize);
+		for ix in 0..search_end {
+			let header = match FrameHeader::parse(data[ix..ix + 4].try_into().unwrap()) {
+				Some(x) => x,
+				None => continue,
+			};
+			let next = ix + header.frame_len();
+			let confirmed = match data.get(next..next + 4) {
+				Some(b) => FrameHeader::parse(b.try_into().unwrap()).is_some_and(|h| h.matches(&header)),
+				None => start + next as u64 >= end,
+			};
+			if !confirmed {
+				continue;
+			}
+			let frame = &data[ix..next.min(data.len())];
+			let offset = start + ix as u64;
+			return Ok(Some(AudioProperties {
+				header,
+				offset,
+				len: end - offset,
+				vbr: VbrHeader::parse(&header, frame),
+			}));
+		}
+		Ok(None)
+	}
+
+	/// The number of decoded samples per channel. Without a frame count the stream is assumed to be CBR. The encoder
+	/// delay and padding recorded by LAME are removed.
+	pub fn samples(&self) -> u64 {
+		let spf = self.header.samples_per_frame() as u64;
+		match self.vbr.as_ref().and_then(|v| v.frames.map(|frames| (v, frames))) {
+			Some((vbr, frames)) => {
+				let trim = vbr.encoder_delay as u64 + vbr.encoder_padding as u64;
+				(frames as u64 * spf).saturating_sub(trim)
+			}
+			None => self.len * 8 * self.header.sample_rate as u64 / (self.header.bitrate as u64 * 1000),
+		}
+	}
+
+	pub fn duration_ms(&self) -> u64 {
+		self.samples() * 1000 / self.header.sample_rate as u64
+	}
+
+	/// The average bitrate in kbit/s.
+	pub fn bitrate(&self) -> u32 {
+		let duration = self.duration_ms();
+		match self.vbr.as_ref().and_then(|v| v.bytes) {
+			Some(bytes) if duration > 0 => (bytes as u64 * 8 / duration) as u32,
+			_ => self.header.bitrate,
+		}
+	}
+}
@@ -0,0 +1,175 @@
+use crate::id3::{self, ID3ChannelVolume, ID3Frame, ID3FrameType, ID3RelativeVolumeFrame, ID3UserTextFrame};
+use crate::mp4::ItemList;
+
+const TRACK_GAIN: &str = "REPLAYGAIN_TRACK_GAIN";
+const TRACK_PEAK: &str = "REPLAYGAIN_TRACK_PEAK";
+const ALBUM_GAIN: &str = "REPLAYGAIN_ALBUM_GAIN";
+const ALBUM_PEAK: &str = "REPLAYGAIN_ALBUM_PEAK";
+
+/// ReplayGain values, independent of where they are stored. Gains are in dB and peaks are linear amplitudes where
+/// 1.0 is full scale.
+///
+/// ID3 tags store them in TXXX frames such as TXXX:REPLAYGAIN_TRACK_GAIN and in RVA2 frames identified as "track" or
+/// "album". MP4 files use freeform items such as ----:com.apple.iTunes:replaygain_track_gain.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ReplayGain {
+	pub track_gain: Option<f32>,
+	pub track_peak: Option<f32>,
+	pub album_gain: Option<f32>,
+	pub album_peak: Option<f32>,
+}
+
+impl ReplayGain {
+	pub fn is_empty(&self) -> bool {
+		*self == ReplayGain::default()
+	}
+
+	/// The values of `self`, falling back to those of `other` where missing.
+	pub fn or(self, other: ReplayGain) -> ReplayGain {
+		ReplayGain {
+			track_gain: self.track_gain.or(other.track_gain),
+			track_peak: self.track_peak.or(other.track_peak),
+			album_gain: self.album_gain.or(other.album_gain),
+			album_peak: self.album_peak.or(other.album_peak),
+		}
+	}
+
+	/// Read from TXXX frames, falling back to RVA2 frames for values they don't have.
+	pub fn from_id3(frames: &[ID3Frame]) -> ReplayGain {
+		let text = |name: &str| {
+			frames.iter().find_map(|frame| match &frame.data {
+				ID3FrameType::UserText(f) if f.description.eq_ignore_ascii_case(name) => {
+					f.values.first().and_then(|v| parse_value(v))
+				}
+				_ => None,
+			})
+		};
+		let from_text = ReplayGain {
+			track_gain: text(TRACK_GAIN),
+			track_peak: text(TRACK_PEAK),
+			album_gain: text(ALBUM_GAIN),
+			album_peak: text(ALBUM_PEAK),
+		};
+
+		let master = |identification: &str| {
+			frames.iter().find_map(|frame| match &frame.data {
+				ID3FrameType::RelativeVolume(f) if f.identification.eq_ignore_ascii_case(identification) => {
+					f.channels.iter().find(|c| c.channel_type == 1)
+				}
+				_ => None,
+			})
+		};
+		let track = master("track");
+		let album = master("album");
+		let from_rva2 = ReplayGain {
+			track_gain: track.map(ID3ChannelVolume::gain_db),
+			track_peak: track.and_then(ID3ChannelVolume::peak_ratio),
+			album_gain: album.map(ID3ChannelVolume::gain_db),
+			album_peak: album.and_then(ID3ChannelVolume::peak_ratio),
+		};
+
+		from_text.or(from_rva2)
+	}
+
+	/// Read from the freeform items of an MP4 item list.
+	pub fn from_item_list(item_list: &ItemList) -> ReplayGain {
+		let text = |name: &str| item_list.freeform_text("com.apple.iTunes", name).and_then(parse_value);
+		ReplayGain {
+			track_gain: text(TRACK_GAIN),
+			track_peak: text(TRACK_PEAK),
+			album_gain: text(ALBUM_GAIN),
+			album_peak: text(ALBUM_PEAK),
+		}
+	}
+
+	/// The conventional names and text values, such as ("REPLAYGAIN_TRACK_GAIN", "-6.50 dB").
+	pub fn text_values(&self) -> Vec<(&'static str, String)> {
+		let mut ret = Vec::new();
+		if let Some(gain) = self.track_gain {
+			ret.push((TRACK_GAIN, format!("{:.2} dB", gain)));
+		}
+		if let Some(peak) = self.track_peak {
+			ret.push((TRACK_PEAK, format!("{:.6}", peak)));
+		}
+		if let Some(gain) = self.album_gain {
+			ret.push((ALBUM_GAIN, format!("{:.2} dB", gain)));
+		}
+		if let Some(peak) = self.album_peak {
+			ret.push((ALBUM_PEAK, format!("{:.6}", peak)));
+		}
+		ret
+	}
+
+	/// TXXX frames for each value, and an RVA2 frame for each of track and album that has a gain.
+	pub fn id3_frames(&self) -> Vec<ID3Frame> {
+		let mut ret: Vec<ID3Frame> = self
+			.text_values()
+			.into_iter()
+			.map(|(name, value)| ID3Frame {
+				id: *b"TXXX",
+				flags: id3::ID3FrameFlags::default(),
+				data: ID3FrameType::UserText(ID3UserTextFrame {
+					description: name.to_string(),
+					values: vec![value],
+					encoding: 0,
+				}),
+			})
+			.collect();
+		for (identification, gain, peak) in [
+			("track", self.track_gain, self.track_peak),
+			("album", self.album_gain, self.album_peak),
+		] {
+			let gain = match gain {
+				Some(x) => x,
+				None => continue,
+			};
+			let (peak_bits, peak) = match peak {
+				Some(x) => ID3ChannelVolume::peak_from_ratio(x),
+				None => (0, Vec::new()),
+			};
+			ret.push(ID3Frame {
+				id: *b"RVA2",
+				flags: id3::ID3FrameFlags::default(),
+				data: ID3FrameType::RelativeVolume(ID3RelativeVolumeFrame {
+					identification: identification.to_string(),
+					channels: vec![ID3ChannelVolume {
+						channel_type: 1, // Master volume
+						adjustment: ID3ChannelVolume::adjustment_from_db(gain),
+						peak_bits,
+						peak,
+					}],
+				}),
+			});
+		}
+		ret
+	}
+
+	/// Replace any ReplayGain frames in `frames` with ones holding these values.
+	pub fn write_id3(&self, frames: &mut Vec<ID3Frame>) {
+		frames.retain(|f| !is_replay_gain_frame(f));
+		frames.extend(self.id3_frames());
+	}
+}
+
+/// Whether the frame is one of the TXXX or RVA2 frames used for ReplayGain.
+pub fn is_replay_gain_frame(frame: &ID3Frame) -> bool {
+	match &frame.data {
+		ID3FrameType::UserText(f) => [TRACK_GAIN, TRACK_PEAK, ALBUM_GAIN, ALBUM_PEAK]
+			.iter()
+			.any(|name| f.description.eq_ignore_ascii_case(name)),
+		ID3FrameType::RelativeVolume(f) => {
+			f.identification.eq_ignore_ascii_case("track") || f.identification.eq_ignore_ascii_case("album")
+		}
+		_ => false,
+	}
+}
+
+/// Parse values such as "-6.50 dB" or "0.988831".
+fn parse_value(value: &str) -> Option<f32> {
+	let value = value.trim();
+	let value = match value.len().checked_sub(2) {
+		Some(end) if value.is_char_boundary(end) && value[end..].eq_ignore_ascii_case("db") => &value[..end],
+		_ => value,
+	};
+	value.trim().parse().ok()
+}
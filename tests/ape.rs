@@ -0,0 +1,152 @@
+use std::io::Cursor;
+
+use tag::{
+	ape::{APETag, APEValue},
+	id3::{ID3FrameType, TextEncoding},
+	TagError,
+};
+
+const HAS_HEADER: u32 = 1 << 31;
+const IS_HEADER: u32 = 1 << 29;
+
+fn item(key: &str, flags: u32, value: &[u8]) -> Vec<u8> {
+	let mut ret = (value.len() as u32).to_le_bytes().to_vec();
+	ret.extend(flags.to_le_bytes());
+	ret.extend(key.as_bytes());
+	ret.push(0);
+	ret.extend(value);
+	ret
+}
+
+fn header(version: u32, items: &[u8], count: u32, flags: u32) -> Vec<u8> {
+	let mut ret = b"APETAGEX".to_vec();
+	ret.extend(version.to_le_bytes());
+	ret.extend((items.len() as u32 + 32).to_le_bytes());
+	ret.extend(count.to_le_bytes());
+	ret.extend(flags.to_le_bytes());
+	ret.extend([0; 8]);
+	ret
+}
+
+/// An APEv2 tag with a header, between some audio and an ID3v1 tag. Returns the file and the end of the APE tag.
+fn file(items: &[Vec<u8>], flags: u32) -> (Vec<u8>, usize) {
+	let body = items.concat();
+	let count = items.len() as u32;
+	let mut ret = b"audio".to_vec();
+	ret.extend(header(2000, &body, count, flags | HAS_HEADER | IS_HEADER));
+	ret.extend(&body);
+	ret.extend(header(2000, &body, count, flags | HAS_HEADER));
+	let end = ret.len();
+	ret.extend(b"TAG");
+	ret.extend([0; 125]);
+	(ret, end)
+}
+
+#[test]
+fn header_footer_and_items_are_read() {
+	let (content, end) = file(
+		&[
+			item("Title", 0, "Café".as_bytes()),
+			item("Artist", 1, b"One\0Two"),
+			item("Cover Art (Front)", 2, b"cover.png\0\x89PNG data"),
+			item("Homepage", 4, b"https://example.com/"),
+		],
+		0,
+	);
+	let (tag, start) = APETag::read(&content, end).unwrap().unwrap();
+	// Stripping the tag keeps the audio before it
+	assert_eq!(&content[..start], b"audio");
+	assert_eq!(tag.version, 2000);
+	assert!(tag.has_header);
+	assert!(!tag.read_only);
+	let keys: Vec<_> = tag.items.iter().map(|i| (i.key.as_str(), i.read_only)).collect();
+	assert_eq!(
+		keys,
+		[
+			("Title", false),
+			("Artist", true),
+			("Cover Art (Front)", false),
+			("Homepage", false)
+		]
+	);
+	assert!(matches!(&tag.items[0].value, APEValue::Text(v) if v == &["Café"]));
+	assert!(matches!(&tag.items[1].value, APEValue::Text(v) if v == &["One", "Two"]));
+	assert!(matches!(&tag.items[2].value, APEValue::Binary(v) if v.starts_with(b"cover.png\0")));
+	assert!(matches!(&tag.items[3].value, APEValue::Locator(url) if url == "https://example.com/"));
+
+	let (streamed, streamed_start) = APETag::read_from(&mut Cursor::new(&content), end as u64)
+		.unwrap()
+		.unwrap();
+	assert_eq!(streamed_start, start as u64);
+	assert_eq!(streamed.items.len(), 4);
+
+	// Not an APE tag
+	assert!(APETag::read(&content, content.len()).unwrap().is_none());
+}
+
+#[test]
+fn items_become_id3_frames() {
+	let (content, end) = file(
+		&[
+			item("title", 0, "Café".as_bytes()),
+			item("Comment", 0, b"Line\0Another"),
+			item("Cover Art (Front)", 2, b"cover\0\x89PNG data"),
+			item("Catalog", 0, b"CAT-1"),
+			item("Homepage", 4, b"https://example.com/"),
+			item("Notes", 2, b"binary"),
+		],
+		0,
+	);
+	let (tag, _) = APETag::read(&content, end).unwrap().unwrap();
+	let frames: Vec<_> = tag.items.iter().map(|i| i.frame()).collect();
+	assert!(frames[5].is_none());
+	let frames: Vec<_> = frames.into_iter().flatten().collect();
+	let ids: Vec<_> = frames
+		.iter()
+		.map(|f| String::from_utf8_lossy(&f.id).into_owned())
+		.collect();
+	assert_eq!(ids, ["TIT2", "COMM", "APIC", "TXXX", "WXXX"]);
+	match &frames[2].data {
+		ID3FrameType::Picture(f) => {
+			assert_eq!((f.mime.as_str(), f.pic_type), ("image/png", 3));
+			assert_eq!(f.data, b"\x89PNG data");
+		}
+		_ => panic!("cover art should be a picture"),
+	}
+
+	let bytes = |i: usize| frames[i].data.bytes(4, TextEncoding::Latin1);
+	assert_eq!(bytes(0), b"\x00Caf\xE9");
+	assert_eq!(bytes(1), b"\x00eng\x00Line\nAnother");
+	assert_eq!(bytes(3), b"\x00Catalog\x00CAT-1");
+	assert_eq!(bytes(4), b"\x00Homepage\x00https://example.com/");
+}
+
+#[test]
+fn flags_and_errors() {
+	let (content, end) = file(&[item("Title", 0, b"Title")], 1);
+	let (tag, _) = APETag::read(&content, end).unwrap().unwrap();
+	assert!(tag.read_only);
+
+	// APEv1 has no header, and its flags are ignored
+	let body = item("Title", 0, b"Title");
+	let mut content = b"audio".to_vec();
+	content.extend(&body);
+	content.extend(header(1000, &body, 1, HAS_HEADER | 1));
+	let (tag, start) = APETag::read(&content, content.len()).unwrap().unwrap();
+	assert_eq!(start, 5);
+	assert!(!tag.has_header && !tag.read_only);
+
+	// An item running into the footer
+	let mut content = body.clone();
+	content.extend(header(1000, &body, 2, 0));
+	assert_eq!(
+		APETag::read(&content, content.len()).err(),
+		Some(TagError::UnexpectedEnd {
+			frame: None,
+			offset: body.len()
+		})
+	);
+	// A size larger than the file
+	content[body.len() + 12] = 0xFF;
+	assert!(APETag::read(&content, content.len()).is_err());
+}
@@ -0,0 +1,97 @@
+use tag::{
+	codepage::{self, Encoding},
+	id3::{ID3CommentFrame, ID3Frame, ID3FrameFlags, ID3FrameType, ID3TextFrame},
+};
+
+mod common;
+use common::{frame, tag};
+
+/// A frame holding `text` encoded in `codepage` but read as ISO-8859-1
+fn mislabelled(id: &[u8; 4], text: &str, codepage: &'static Encoding) -> ID3Frame {
+	let bytes = codepage.encode(text).0;
+	ID3Frame {
+		id: *id,
+		flags: ID3FrameFlags::default(),
+		data: ID3FrameType::Text(ID3TextFrame {
+			values: vec![bytes.iter().map(|b| *b as char).collect()],
+			encoding: 0,
+		}),
+	}
+}
+
+fn detect(title: &str, artist: &str, codepage: &str) -> Option<&'static str> {
+	let codepage = codepage::for_name(codepage).unwrap();
+	let frames = [
+		mislabelled(b"TIT2", title, codepage),
+		mislabelled(b"TPE1", artist, codepage),
+	];
+	codepage::detect(&frames).map(|c| c.name())
+}
+
+#[test]
+fn detects_legacy_codepages() {
+	assert_eq!(detect("Группа крови", "Кино", "CP1251"), Some("windows-1251"));
+	assert_eq!(detect("さくらんぼ", "大塚愛", "Shift_JIS"), Some("Shift_JIS"));
+	assert_eq!(detect("七里香", "周杰伦", "GBK"), Some("GBK"));
+	assert_eq!(detect("七里香", "周杰倫", "Big5"), Some("Big5"));
+}
+
+#[test]
+fn latin1_and_ascii_are_left_alone() {
+	assert_eq!(detect("Jóga", "Björk", "ISO-8859-1"), None);
+	assert_eq!(detect("Ça plane pour moi", "Plastic Bertrand", "ISO-8859-1"), None);
+	assert_eq!(detect("Title", "Artist", "ISO-8859-1"), None);
+}
+
+#[test]
+fn decodes_mislabelled_frames() {
+	let cp1251 = codepage::for_name("cp1251").unwrap();
+	let mut frames = vec![
+		mislabelled(b"TIT2", "Кукушка", cp1251),
+		ID3Frame {
+			id: *b"COMM",
+			flags: ID3FrameFlags::default(),
+			data: ID3FrameType::Comment(ID3CommentFrame {
+				language: *b"rus",
+				content_desc: String::new(),
+				text: "Цой".to_string(),
+				// Already decoded, so left alone
+				encoding: 1,
+			}),
+		},
+	];
+	codepage::decode_frames(&mut frames, cp1251);
+	match &frames[0].data {
+		ID3FrameType::Text(f) => assert_eq!(f.values, ["Кукушка"]),
+		_ => panic!("TIT2 should be text"),
+	}
+	match &frames[1].data {
+		ID3FrameType::Comment(f) => assert_eq!(f.text, "Цой"),
+		_ => panic!("COMM should be a comment"),
+	}
+}
+
+#[test]
+fn reads_tags_in_a_codepage() {
+	let cp1251 = codepage::for_name("cp1251").unwrap();
+	let mut body = Vec::new();
+	for (id, text) in [(b"TIT2", "Кукушка"), (b"TIT3", "Концерт"), (b"TPE3", "Цой")] {
+		body.extend(frame(3, id, [0, 0], &[&[0], &cp1251.encode(text).0[..]].concat()));
+	}
+	let content = tag(3, 0, &body);
+
+	let (frames, size) = tag::read_id3_frames_in_codepage(&content, cp1251).unwrap();
+	assert_eq!(size, content.len());
+	let values: Vec<_> = frames
+		.iter()
+		.map(|f| match &f.data {
+			ID3FrameType::Text(t) => t.values.join("/"),
+			_ => panic!("{} should be text", String::from_utf8_lossy(&f.id)),
+		})
+		.collect();
+	assert_eq!(values, ["Кукушка", "Концерт", "Цой"]);
+
+	let streamed = tag::read_id3_frames_in_codepage_from(&mut std::io::Cursor::new(&content), cp1251).unwrap();
+	let display = |frames: &[ID3Frame]| frames.iter().map(ID3Frame::display).collect::<Vec<_>>();
+	assert_eq!(display(&streamed.0), display(&frames));
+}
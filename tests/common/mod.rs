@@ -0,0 +1,32 @@
+//! Builders for the ID3v2 tags and frames used by the tests.
+#![allow(dead_code)]
+
+pub fn synchsafe(n: usize) -> [u8; 4] {
+	[
+		(n >> 21) as u8 & 0x7F,
+		(n >> 14) as u8 & 0x7F,
+		(n >> 7) as u8 & 0x7F,
+		n as u8 & 0x7F,
+	]
+}
+
+/// A tag of `version` holding `body`
+pub fn tag(version: u8, flags: u8, body: &[u8]) -> Vec<u8> {
+	let mut ret = vec![b'I', b'D', b'3', version, 0, flags];
+	ret.extend(synchsafe(body.len()));
+	ret.extend(body);
+	ret
+}
+
+/// A frame for a tag of `version` with the given content
+pub fn frame(version: u8, id: &[u8; 4], flags: [u8; 2], content: &[u8]) -> Vec<u8> {
+	let mut ret = id.to_vec();
+	if version == 3 {
+		ret.extend((content.len() as u32).to_be_bytes());
+	} else {
+		ret.extend(synchsafe(content.len()));
+	}
+	ret.extend(flags);
+	ret.extend(content);
+	ret
+}
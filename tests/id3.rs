@@ -0,0 +1,527 @@
+use tag::{
+	id3::{self, ID3FrameType},
+	TagError,
+};
+
+mod common;
+use common::{frame, synchsafe, tag};
+
+#[test]
+fn embedded_frames_are_resynchronised_once() {
+	let apic = frame(4, b"APIC", [0, 0], b"\x00image/png\x00\x03\x00\xFF\x00\x01");
+	let mut chap = b"ch0\x00".to_vec();
+	chap.extend([0; 16]);
+	chap.extend(apic);
+	let content = tag(
+		4,
+		0b1000_0000,
+		&frame(4, b"CHAP", [0, 0b10], &id3::unsynchronise(&chap)),
+	);
+
+	let tag = tag::read_id3_tag(&content).unwrap();
+	let chapter = match &tag.frames[0].data {
+		ID3FrameType::Chapter(f) => f,
+		_ => panic!("CHAP not parsed"),
+	};
+	match &chapter.frames[0].data {
+		ID3FrameType::Picture(f) => assert_eq!(f.data, [0xFF, 0x00, 0x01]),
+		_ => panic!("APIC not parsed"),
+	}
+	assert!(chapter.frames[0].flags.is_empty());
+	assert_eq!(tag.bytes(), content);
+}
+
+#[test]
+fn general_objects_are_written_as_read() {
+	// A big-endian filename and an empty description without a byte order mark
+	let geob = b"\x01application/octet-stream\x00\xFE\xFF\x00a\x00\x00\x00\x00\x01\x02";
+	let content = tag(4, 0, &frame(4, b"GEOB", [0, 0], geob));
+
+	let tag = tag::read_id3_tag(&content).unwrap();
+	match &tag.frames[0].data {
+		ID3FrameType::GeneralObject(f) => {
+			assert_eq!(f.mime, "application/octet-stream");
+			assert_eq!(f.filename, "a");
+			assert_eq!(f.filename_bom, id3::ByteOrderMark::BigEndian);
+			assert_eq!(f.description, "");
+			assert_eq!(f.description_bom, id3::ByteOrderMark::Omitted);
+			assert_eq!(f.data, [1, 2]);
+		}
+		_ => panic!("GEOB not parsed"),
+	}
+	assert_eq!(tag.bytes(), content);
+}
+
+#[test]
+fn errors_give_the_frame_and_offset() {
+	let title = frame(4, b"TIT2", [0, 0], b"\x00Title");
+	// The description of the comment is UTF-16 without a byte order mark. It starts after the tag header, the title,
+	// the frame header, the encoding and the language.
+	let mut body = title.clone();
+	body.extend(frame(4, b"COMM", [0, 0], b"\x01engA\x00\x00\x00"));
+	let err = tag::read_id3_tag(&tag(4, 0, &body)).err().unwrap();
+	assert_eq!(
+		err,
+		TagError::MissingBom {
+			frame: Some(*b"COMM"),
+			offset: 40,
+			found: [b'A', 0],
+		}
+	);
+	assert_eq!(
+		err.to_string(),
+		"Expected FF FE or FE FF in frame COMM at offset 40, got 41 00"
+	);
+
+	let mut body = title.clone();
+	body.extend(frame(4, b"TPE1", [0, 0], b"\x05Artist"));
+	assert_eq!(
+		tag::read_id3_tag(&tag(4, 0, &body)).err(),
+		Some(TagError::InvalidEncoding {
+			frame: Some(*b"TPE1"),
+			offset: 37,
+			encoding: 5,
+		})
+	);
+
+	// The frame claims more data than the tag holds
+	let mut body = title.clone();
+	body.extend(&frame(4, b"TALB", [0, 0], b"\x00Album")[..12]);
+	assert_eq!(
+		tag::read_id3_tag(&tag(4, 0, &body)).err(),
+		Some(TagError::UnexpectedEnd {
+			frame: Some(*b"TALB"),
+			offset: 36,
+		})
+	);
+
+	// The valid frames before the error read and write back unchanged
+	let content = tag(4, 0, &title);
+	let tag = tag::read_id3_tag(&content).unwrap();
+	assert_eq!(tag.frames[0].display(), "TIT2:Title");
+	assert_eq!(tag.bytes(), content);
+}
+
+fn picture_data(tag: &id3::ID3v240Tag) -> &[u8] {
+	match &tag.frames[0].data {
+		ID3FrameType::Picture(f) => &f.data,
+		_ => panic!("APIC not parsed"),
+	}
+}
+
+#[test]
+fn unsynchronisation_round_trips() {
+	let data = [0xFF, 0xE0, 0xFF, 0x00, 0xFF, 0x01, 0xFF];
+	let unsynchronised = [0xFF, 0x00, 0xE0, 0xFF, 0x00, 0x00, 0xFF, 0x01, 0xFF, 0x00];
+	assert_eq!(id3::unsynchronise(&data), unsynchronised);
+	assert_eq!(id3::resynchronise(&unsynchronised), data);
+
+	let apic = [&b"\x00image/png\x00\x03\x00"[..], &data].concat();
+
+	// The whole tag in ID3v2.3
+	let content = tag(3, 0b1000_0000, &id3::unsynchronise(&frame(3, b"APIC", [0, 0], &apic)));
+	let read = tag::read_id3_tag(&content).unwrap();
+	assert_eq!(picture_data(&read), data);
+	assert_eq!(read.bytes(), content);
+
+	// Each frame in ID3v2.4
+	let content = tag(4, 0, &frame(4, b"APIC", [0, 0b10], &id3::unsynchronise(&apic)));
+	let read = tag::read_id3_tag(&content).unwrap();
+	assert_eq!(picture_data(&read), data);
+	assert!(read.frames[0].flags.unsynchronisation);
+	assert_eq!(read.bytes(), content);
+}
+
+#[test]
+fn crc_is_checked_and_written() {
+	assert_eq!(id3::crc32(b"123456789"), 0xCBF4_3926);
+
+	// ID3v2.3 calculates the CRC over the frames
+	let title = frame(3, b"TIT2", [0, 0], b"\x00Title");
+	let mut body = vec![0, 0, 0, 10, 0x80, 0, 0, 0, 0, 0, 0x4A, 0x17, 0x66, 0xBE];
+	body.extend(&title);
+	let content = tag(3, 0b0100_0000, &body);
+	let read = tag::read_id3_tag(&content).unwrap();
+	assert_eq!(read.extended_header.as_ref().unwrap().crc, Some(0x4A17_66BE));
+	assert_eq!(read.bytes(), content);
+
+	let mut corrupted = content.clone();
+	*corrupted.last_mut().unwrap() = b'E';
+	assert_eq!(
+		tag::read_id3_tag(&corrupted).err(),
+		Some(TagError::CrcMismatch {
+			expected: 0x4A17_66BE,
+			calculated: id3::crc32(&corrupted[24..]),
+		})
+	);
+
+	// ID3v2.4 includes the padding, and stores the CRC as a 35 bit synchsafe integer
+	let mut body = vec![0, 0, 0, 12, 1, 0b0010_0000, 5, 0, 25, 101, 122, 63];
+	body.extend(frame(4, b"TIT2", [0, 0], b"\x00Title"));
+	body.extend([0; 4]);
+	let content = tag(4, 0b0100_0000, &body);
+	let read = tag::read_id3_tag(&content).unwrap();
+	assert_eq!(read.extended_header.as_ref().unwrap().crc, Some(0x0339_7D3F));
+	assert_eq!(read.padding, 4);
+	assert_eq!(read.bytes(), content);
+}
+
+fn text_values(tag: &id3::ID3v240Tag) -> Vec<String> {
+	match &tag.frames[0].data {
+		ID3FrameType::Text(f) => f.values.clone(),
+		_ => panic!("{} should be text", String::from_utf8_lossy(&tag.frames[0].id)),
+	}
+}
+
+#[test]
+fn compressed_grouped_and_encrypted_frames() {
+	let text = b"\x00A title long enough to compress, compress, compress";
+	let compressed = id3::compress(text);
+
+	// ID3v2.4: group, then data length indicator, then the compressed content
+	let mut content = vec![0x05];
+	content.extend(synchsafe(text.len()));
+	content.extend(&compressed);
+	let content = tag(4, 0, &frame(4, b"TIT2", [0b0100_0000, 0b0100_1001], &content));
+	let read = tag::read_id3_tag(&content).unwrap();
+	assert_eq!(
+		text_values(&read),
+		["A title long enough to compress, compress, compress"]
+	);
+	let flags = read.frames[0].flags;
+	assert!(flags.tag_alter_preservation && flags.compression);
+	assert_eq!(flags.grouping_identity, Some(5));
+	assert_eq!(flags.data_length_indicator, Some(text.len() as u32));
+	assert_eq!(read.bytes(), content);
+
+	// ID3v2.3: decompressed size, then group
+	let mut content = (text.len() as u32).to_be_bytes().to_vec();
+	content.push(0x07);
+	content.extend(&compressed);
+	let content = tag(3, 0, &frame(3, b"TIT2", [0b0010_0000, 0b1010_0000], &content));
+	let read = tag::read_id3_tag(&content).unwrap();
+	assert_eq!(
+		text_values(&read),
+		["A title long enough to compress, compress, compress"]
+	);
+	assert!(read.frames[0].flags.read_only);
+	assert_eq!(read.frames[0].flags.grouping_identity, Some(7));
+	assert_eq!(read.bytes(), content);
+
+	// Encrypted content is kept as it is, after the method and data length indicator
+	let mut content = vec![0x80];
+	content.extend(synchsafe(100));
+	content.extend(b"\x01\x02\x03");
+	let content = tag(4, 0, &frame(4, b"TIT2", [0, 0b0000_0101], &content));
+	let read = tag::read_id3_tag(&content).unwrap();
+	assert!(matches!(&read.frames[0].data, ID3FrameType::Raw(data) if data == b"\x01\x02\x03"));
+	assert_eq!(read.frames[0].flags.encryption, Some(0x80));
+	assert_eq!(read.frames[0].flags.data_length_indicator, Some(100));
+	assert_eq!(read.bytes(), content);
+}
+
+/// UTF-16 with a little-endian byte order mark
+fn utf16(s: &str) -> Vec<u8> {
+	[0xFF, 0xFE]
+		.into_iter()
+		.chain(s.encode_utf16().flat_map(|c| c.to_le_bytes()))
+		.collect()
+}
+
+#[test]
+fn user_text_in_each_encoding() {
+	let utf16_be = |s: &str| s.encode_utf16().flat_map(|c| c.to_be_bytes()).collect::<Vec<u8>>();
+	let utf16_txxx = [
+		&[1][..],
+		&utf16("CATALOG"),
+		&[0, 0],
+		&utf16("ABC-123"),
+		&[0, 0],
+		&utf16("Second"),
+	]
+	.concat();
+	let contents = [
+		b"\x00CATALOG\x00ABC-123\x00Second".to_vec(),
+		utf16_txxx.clone(),
+		[
+			&[2][..],
+			&utf16_be("CATALOG"),
+			&[0, 0],
+			&utf16_be("ABC-123"),
+			&[0, 0],
+			&utf16_be("Second"),
+		]
+		.concat(),
+		b"\x03CATALOG\x00ABC-123\x00Second".to_vec(),
+	];
+	for txxx in contents {
+		let read = tag::read_id3_tag(&tag(4, 0, &frame(4, b"TXXX", [0, 0], &txxx))).unwrap();
+		match &read.frames[0].data {
+			ID3FrameType::UserText(f) => {
+				assert_eq!(f.description, "CATALOG");
+				assert_eq!(f.values, ["ABC-123", "Second"]);
+			}
+			_ => panic!("TXXX not parsed"),
+		}
+		assert_eq!(read.frames[0].display(), "TXXX:CATALOG=ABC-123;Second");
+
+		// Written in the preferred encoding
+		let data = &read.frames[0].data;
+		assert_eq!(
+			data.bytes(4, id3::TextEncoding::Latin1),
+			b"\x00CATALOG\x00ABC-123\x00Second"
+		);
+		assert_eq!(data.bytes(3, id3::TextEncoding::Utf16), utf16_txxx);
+	}
+}
+
+#[test]
+fn url_frames() {
+	let mut body = frame(4, b"WOAF", [0, 0], b"http://example.com/ep1.mp3");
+	// Some writers terminate the URL
+	body.extend(frame(4, b"WPUB", [0, 0], b"http://example.com/\x00"));
+	body.extend(frame(
+		4,
+		b"WXXX",
+		[0, 0],
+		&[&[1][..], &utf16("Feed"), &[0, 0], b"http://example.com/feed.xml"].concat(),
+	));
+	let read = tag::read_id3_tag(&tag(4, 0, &body)).unwrap();
+	let displayed: Vec<String> = read.frames.iter().map(|f| f.display()).collect();
+	assert_eq!(
+		displayed,
+		[
+			"WOAF:http://example.com/ep1.mp3",
+			"WPUB:http://example.com/",
+			"WXXX:Feed=http://example.com/feed.xml",
+		]
+	);
+
+	let mut expected = frame(4, b"WOAF", [0, 0], b"http://example.com/ep1.mp3");
+	expected.extend(frame(4, b"WPUB", [0, 0], b"http://example.com/"));
+	expected.extend(frame(4, b"WXXX", [0, 0], b"\x00Feed\x00http://example.com/feed.xml"));
+	assert_eq!(read.bytes(), tag(4, 0, &expected));
+}
+
+#[test]
+fn unique_file_ids_and_private_frames() {
+	let mut body = frame(4, b"UFID", [0, 0], b"http://musicbrainz.org\x00f8b7a3c2-1234");
+	body.extend(frame(4, b"UFID", [0, 0], b"http://example.com\x00\x00\x01\x02"));
+	body.extend(frame(4, b"PRIV", [0, 0], b"WM/MediaClassPrimaryID\x00\xBC\x7D\x60\xD1"));
+	let content = tag(4, 0, &body);
+	let read = tag::read_id3_tag(&content).unwrap();
+
+	let musicbrainz = id3::find_by_owner(&read.frames, b"UFID", "http://musicbrainz.org").unwrap();
+	match &musicbrainz.data {
+		ID3FrameType::UniqueFileId(f) => assert_eq!(f.identifier, b"f8b7a3c2-1234"),
+		_ => panic!("UFID not parsed"),
+	}
+	match &id3::find_by_owner(&read.frames, b"UFID", "http://example.com")
+		.unwrap()
+		.data
+	{
+		ID3FrameType::UniqueFileId(f) => assert_eq!(f.identifier, [0, 1, 2]),
+		_ => panic!("UFID not parsed"),
+	}
+	match &read.frames[2].data {
+		ID3FrameType::Private(f) => {
+			assert_eq!(f.owner, "WM/MediaClassPrimaryID");
+			assert_eq!(f.data, [0xBC, 0x7D, 0x60, 0xD1]);
+		}
+		_ => panic!("PRIV not parsed"),
+	}
+	assert_eq!(read.frames[2].display(), "PRIV:WM/MediaClassPrimaryID=4 bytes");
+	assert_eq!(read.bytes(), content);
+}
+
+#[test]
+fn popularimeter_and_play_counter() {
+	let mut body = frame(4, b"POPM", [0, 0], b"me@example.com\x00\xC4\x00\x00\x01\x00");
+	body.extend(frame(4, b"POPM", [0, 0], b"rating@example.com\x00\x40"));
+	// Counters grow a byte at a time past 32 bits
+	body.extend(frame(4, b"PCNT", [0, 0], &[0x01, 0x00, 0x00, 0x00, 0x00]));
+	let content = tag(4, 0, &body);
+	let read = tag::read_id3_tag(&content).unwrap();
+	match &read.frames[0].data {
+		ID3FrameType::Popularimeter(f) => {
+			assert_eq!(f.email, "me@example.com");
+			assert_eq!(f.rating, 0xC4);
+			assert_eq!(f.counter, Some(256));
+		}
+		_ => panic!("POPM not parsed"),
+	}
+	assert_eq!(read.frames[1].display(), "POPM:rating@example.com=64/255");
+	assert_eq!(read.frames[2].display(), "PCNT:4294967296 plays");
+	assert!(id3::find_by_owner(&read.frames, b"POPM", "rating@example.com").is_some());
+	assert_eq!(read.bytes(), content);
+
+	// Counters too large for 64 bits are kept raw
+	let content = tag(4, 0, &frame(4, b"PCNT", [0, 0], &[1; 9]));
+	let read = tag::read_id3_tag(&content).unwrap();
+	assert!(matches!(&read.frames[0].data, ID3FrameType::Raw(data) if data == &[1; 9]));
+	assert_eq!(read.bytes(), content);
+}
+
+#[test]
+fn synchronised_lyrics() {
+	let mut sylt = b"\x00eng\x02\x01Lyrics\x00".to_vec();
+	sylt.extend(b"Hello\x00\x00\x00\x03\xE8");
+	sylt.extend(b"World\x00\x00\x00\x09\xC4");
+	let content = tag(4, 0, &frame(4, b"SYLT", [0, 0], &sylt));
+	let read = tag::read_id3_tag(&content).unwrap();
+	match &read.frames[0].data {
+		ID3FrameType::SyncLyrics(f) => {
+			assert_eq!(&f.language, b"eng");
+			assert_eq!((f.timestamp_format, f.content_type), (2, 1));
+			assert_eq!(f.description, "Lyrics");
+			assert_eq!(f.lyrics, [(String::from("Hello"), 1000), (String::from("World"), 2500)]);
+			assert_eq!(tag::lrc::to_lrc(&f.lyrics), "[00:01.00]Hello\n[00:02.50]World\n");
+		}
+		_ => panic!("SYLT not parsed"),
+	}
+	assert_eq!(read.frames[0].display(), "SYLT:Lyrics:eng:2 lines");
+	assert_eq!(read.bytes(), content);
+}
+
+#[test]
+fn padding_fills_the_old_space() {
+	let title = frame(4, b"TIT2", [0, 0], b"\x00Title");
+	let mut content = tag(4, 0b0001_0000, &title);
+	content.extend(b"3DI\x04\x00\x10\x00\x00\x00\x10");
+	let mut read = tag::read_id3_tag(&content).unwrap();
+	assert!(read.has_footer);
+	assert_eq!(read.size(), 36);
+	assert_eq!(read.bytes(), content);
+
+	// Too small for the header and the frame
+	assert!(!read.pad_to(25));
+	assert_eq!(read.bytes(), content);
+
+	assert!(read.pad_to(100));
+	assert_eq!(read.padding, 74);
+	let mut expected = tag(4, 0, &title);
+	expected.resize(100, 0);
+	expected[9] = 90;
+	assert_eq!(read.bytes(), expected);
+	let reread = tag::read_id3_tag(&expected).unwrap();
+	assert_eq!((reread.padding, reread.has_footer, reread.size()), (74, false, 100));
+
+	// Exactly the space of the frames leaves no room for padding, and a footer is only written without padding
+	assert!(read.pad_to(26));
+	assert_eq!(read.bytes(), tag(4, 0, &title));
+
+	// In ID3v2.3 the padding follows the unsynchronised frames
+	let picture = frame(3, b"APIC", [0, 0], b"\x00image/png\x00\x03\x00\xFF\xE0");
+	let mut read = tag::read_id3_tag(&tag(3, 0b1000_0000, &id3::unsynchronise(&picture))).unwrap();
+	assert!(read.pad_to(64));
+	let written = read.bytes();
+	assert_eq!(written.len(), 64);
+	assert_eq!(tag::read_id3_tag(&written).unwrap().padding, read.padding);
+	assert_eq!(picture_data(&tag::read_id3_tag(&written).unwrap()), [0xFF, 0xE0]);
+}
+
+#[test]
+fn tags_are_streamed_as_read() {
+	let mut body = frame(4, b"TIT2", [0, 0], b"\x00Title");
+	body.extend([0; 20]);
+	let mut content = tag(4, 0, &body);
+	let tag_size = content.len();
+	content.extend(b"audio data");
+
+	let read = tag::read_id3_tag(&content).unwrap();
+	let mut reader = std::io::Cursor::new(&content);
+	let streamed = tag::read_id3_tag_from(&mut reader).unwrap();
+	assert_eq!((streamed.size(), streamed.padding), (read.size(), 20));
+	assert_eq!(text_values(&streamed), ["Title"]);
+	assert_eq!(streamed.bytes(), read.bytes());
+	assert_eq!(streamed.bytes(), &content[..tag_size]);
+	// Only the tag is read
+	assert_eq!(reader.position(), tag_size as u64);
+
+	// The footer follows the size given in the header
+	let mut with_footer = tag(4, 0b0001_0000, &frame(4, b"TIT2", [0, 0], b"\x00Title"));
+	with_footer.extend(b"3DI\x04\x00\x10\x00\x00\x00\x10");
+	let footer_size = with_footer.len();
+	with_footer.extend(b"audio data");
+	let mut reader = std::io::Cursor::new(&with_footer);
+	let streamed = tag::read_id3_tag_from(&mut reader).unwrap();
+	assert!(streamed.has_footer);
+	assert_eq!(reader.position(), footer_size as u64);
+	assert_eq!(streamed.bytes(), &with_footer[..footer_size]);
+
+	let mut audio = Vec::new();
+	let copied = tag::copy_range(&mut reader, &mut audio, footer_size as u64, with_footer.len() as u64).unwrap();
+	assert_eq!((copied, &audio[..]), (10, &b"audio data"[..]));
+	let past_end = tag::copy_range(&mut reader, &mut audio, 0, with_footer.len() as u64 + 1);
+	assert_eq!(past_end.unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+
+	assert_eq!(
+		tag::read_id3_tag_from(&mut std::io::Cursor::new(b"ID3")).err(),
+		Some(TagError::MissingHeader)
+	);
+}
+
+#[test]
+fn latin1_text_is_read_and_written() {
+	let latin1: Vec<u8> = (0x80..=0xFF).collect();
+	let mut content = vec![0];
+	content.extend(&latin1);
+	let mut read = tag::read_id3_tag(&tag(4, 0, &frame(4, b"TIT2", [0, 0], &content))).unwrap();
+	let title = text_values(&read).remove(0);
+	assert_eq!(title.chars().count(), 128);
+	assert!(title.chars().zip(0x80..).all(|(c, b)| c as u32 == b));
+	assert!(title.ends_with("ýþÿ"));
+	assert_eq!(read.frames[0].data.bytes(4, id3::TextEncoding::Latin1), content);
+
+	// Latin-1 can't hold the euro sign, so the preference decides what is written instead
+	match &mut read.frames[0].data {
+		ID3FrameType::Text(f) => f.values = vec!["5 € or £4".to_string()],
+		_ => panic!("TIT2 should be text"),
+	}
+	let written = |version: u8, text_encoding: id3::TextEncoding| read.frames[0].data.bytes(version, text_encoding);
+	assert_eq!(
+		written(4, id3::TextEncoding::Latin1),
+		b"\x035 \xE2\x82\xAC or \xC2\xA34"
+	);
+	assert_eq!(written(4, id3::TextEncoding::Utf16BE)[..5], [2, 0, b'5', 0, b' ']);
+	assert_eq!(written(3, id3::TextEncoding::Utf16BE)[..5], [1, 0xFF, 0xFE, b'5', 0]);
+	assert_eq!(written(3, id3::TextEncoding::Utf8)[..3], [1, 0xFF, 0xFE]);
+
+	let choose = |version: u8, text_encoding: id3::TextEncoding, s: &str| text_encoding.choose(version, &[s]);
+	assert_eq!(choose(3, id3::TextEncoding::Latin1, "Café"), 0);
+	assert_eq!(choose(3, id3::TextEncoding::Latin1, "€"), 1);
+	assert_eq!(choose(4, id3::TextEncoding::Latin1, "€"), 3);
+	assert_eq!(choose(4, id3::TextEncoding::Utf16, "Café"), 1);
+	assert_eq!(choose(4, id3::TextEncoding::Utf16BE, "Café"), 2);
+	assert_eq!(choose(4, id3::TextEncoding::Utf8, "Café"), 3);
+
+	// ID3v1 is always Latin-1
+	let v1 = tag::id3v1::ID3v1Tag::from_frames(&read.frames);
+	assert_eq!(v1.bytes()[3..13], *b"5 ? or \xA34\x00");
+}
+
+#[test]
+fn table_of_contents_holds_at_most_255_children() {
+	let ctoc = id3::ID3Frame {
+		id: *b"CTOC",
+		flags: id3::ID3FrameFlags::default(),
+		data: ID3FrameType::TableOfContents(id3::ID3TableOfContentsFrame {
+			element_id: String::from("toc"),
+			top_level: true,
+			ordered: true,
+			children: (0..300).map(|i| format!("chp{}", i)).collect(),
+			frames: Vec::new(),
+		}),
+	};
+	let written = ctoc.bytes(4, id3::TextEncoding::default());
+	assert_eq!(written.len(), 10 + ctoc.data.len());
+
+	let tag = tag::read_id3_tag(&tag(4, 0, &written)).unwrap();
+	match &tag.frames[0].data {
+		ID3FrameType::TableOfContents(f) => {
+			assert_eq!(f.children.len(), 255);
+			assert_eq!(f.children.last().unwrap(), "chp254");
+		}
+		_ => panic!("CTOC not parsed"),
+	}
+}
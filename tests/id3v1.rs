@@ -0,0 +1,118 @@
+use std::io::Cursor;
+
+use tag::{
+	id3::{ID3Frame, ID3FrameType},
+	id3v1::{self, ID3v1Tag},
+};
+
+/// `s` padded with `pad` to `len` bytes
+fn field(s: &[u8], len: usize, pad: u8) -> Vec<u8> {
+	let mut ret = s.to_vec();
+	ret.resize(len, pad);
+	ret
+}
+
+/// An ID3v1.1 tag with the artist padded with `pad`
+fn v11_tag(pad: u8) -> Vec<u8> {
+	let mut ret = b"TAG".to_vec();
+	ret.extend(field(b"Title", 30, 0));
+	ret.extend(field(b"Artist", 30, pad));
+	ret.extend(field(b"Caf\xE9", 30, 0));
+	ret.extend(b"1999");
+	ret.extend(field(b"Comment", 28, 0));
+	ret.extend([0, 7, 17]);
+	ret
+}
+
+#[test]
+fn v11_track_is_read_and_written() {
+	let mut content = b"audio".to_vec();
+	// Some writers pad with spaces, which are written back as NULs
+	content.extend(v11_tag(b' '));
+	let tag = ID3v1Tag::read(&content).unwrap();
+	assert_eq!(
+		[&tag.title, &tag.artist, &tag.album, &tag.year, &tag.comment],
+		["Title", "Artist", "Café", "1999", "Comment"]
+	);
+	assert_eq!(tag.track, Some(7));
+	assert_eq!(tag.genre, 17);
+	assert!(tag.extended.is_none());
+	assert_eq!(tag.size(), 128);
+
+	let streamed = ID3v1Tag::read_from(&mut Cursor::new(&content)).unwrap().unwrap();
+	assert_eq!(streamed.bytes(), tag.bytes());
+	assert_eq!(tag.bytes(), v11_tag(0));
+}
+
+#[test]
+fn v10_comment_takes_the_track_bytes() {
+	let mut content = v11_tag(0);
+	content[97..127].copy_from_slice(&field(b"A comment of exactly thirty b.", 30, 0));
+	let tag = ID3v1Tag::read(&content).unwrap();
+	assert_eq!(tag.comment, "A comment of exactly thirty b.");
+	assert_eq!(tag.track, None);
+	assert_eq!(tag.bytes(), content);
+
+	assert!(ID3v1Tag::read(&content[1..]).is_none());
+	assert!(ID3v1Tag::read(b"TAG").is_none());
+}
+
+#[test]
+fn extended_tag_continues_the_fields() {
+	let title = "A title which is longer than thirty characters";
+	let mut content = b"TAG+".to_vec();
+	content.extend(field(&title.as_bytes()[30..], 60, 0));
+	content.extend(field(b"", 60, 0));
+	content.extend(field(b"", 60, 0));
+	content.push(3);
+	content.extend(field(b"Chiptune", 30, 0));
+	content.extend(b"001:30002:45");
+	let mut v1 = b"TAG".to_vec();
+	v1.extend(field(&title.as_bytes()[..30], 30, 0));
+	v1.extend(field(b"", 94, 0));
+	v1.push(255);
+	content.extend(v1);
+	assert_eq!(content.len(), 355);
+
+	let tag = ID3v1Tag::read(&content).unwrap();
+	let ext = tag.extended.as_ref().unwrap();
+	assert_eq!(ext.title, &title[30..]);
+	assert_eq!(ext.speed, 3);
+	assert_eq!(ext.genre, "Chiptune");
+	assert_eq!([&ext.start_time, &ext.end_time], ["001:30", "002:45"]);
+	assert_eq!(tag.size(), 355);
+	assert_eq!(tag.bytes(), content);
+
+	let display: Vec<_> = tag.frames().iter().map(ID3Frame::display).collect();
+	assert_eq!(display, [format!("TIT2:{}", title), "TCON:Chiptune".to_string()]);
+}
+
+#[test]
+fn frames_convert_both_ways() {
+	let tag = ID3v1Tag::read(&v11_tag(0)).unwrap();
+	let frames = tag.frames();
+	let display: Vec<_> = frames.iter().map(ID3Frame::display).collect();
+	assert_eq!(
+		display,
+		[
+			"TIT2:Title",
+			"TPE1:Artist",
+			"TALB:Café",
+			"TDRC:1999",
+			"TRCK:7",
+			"TCON:Rock",
+			"COMM::eng:Comment"
+		]
+	);
+	assert!(frames.iter().all(|f| match &f.data {
+		ID3FrameType::Text(t) => t.encoding == 0,
+		ID3FrameType::Comment(c) => c.encoding == 0,
+		_ => false,
+	}));
+	assert_eq!(ID3v1Tag::from_frames(&frames).bytes(), tag.bytes());
+
+	assert_eq!(id3v1::parse_genre("(17)"), 17);
+	assert_eq!(id3v1::parse_genre("rock"), 17);
+	assert_eq!(id3v1::parse_genre("Chiptune"), 255);
+	assert_eq!(id3v1::genre_name(255), None);
+}
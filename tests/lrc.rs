@@ -0,0 +1,25 @@
+use tag::lrc::{from_lrc, to_lrc};
+
+#[test]
+fn lrc_is_read_in_time_order() {
+	let lrc = "[ar:Artist]\n[ti:Title]\n[00:12.50]First line\n[01:02.3][00:30.125]Chorus\n\nNot timed\n";
+	assert_eq!(
+		from_lrc(lrc),
+		[
+			(String::from("First line"), 12_500),
+			(String::from("Chorus"), 30_125),
+			(String::from("Chorus"), 62_300),
+		]
+	);
+}
+
+#[test]
+fn lrc_is_written_in_hundredths() {
+	let lyrics = [(String::from("\nFirst line"), 12_504), (String::from("Second"), 61_000)];
+	let lrc = to_lrc(&lyrics);
+	assert_eq!(lrc, "[00:12.50]First line\n[01:01.00]Second\n");
+	assert_eq!(
+		from_lrc(&lrc),
+		[(String::from("First line"), 12_500), (String::from("Second"), 61_000)]
+	);
+}
@@ -0,0 +1,94 @@
+use std::io::Cursor;
+
+use proptest::prelude::*;
+use tag::{ape::APETag, id3v1::ID3v1Tag};
+
+mod common;
+use common::{frame, tag};
+
+/// Each file in tests/malformed is a tag with one defect, named after it.
+#[test]
+fn malformed_corpus_is_rejected() {
+	let mut paths: Vec<_> = std::fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/malformed"))
+		.unwrap()
+		.map(|entry| entry.unwrap().path())
+		.collect();
+	paths.sort();
+	assert!(!paths.is_empty());
+	for path in paths {
+		let content = std::fs::read(&path).unwrap();
+		let result = tag::read_id3_tag(&content);
+		assert!(result.is_err(), "{} was read", path.display());
+		let streamed = tag::read_id3_tag_from(&mut Cursor::new(&content));
+		assert_eq!(streamed.err(), result.err(), "{}", path.display());
+	}
+}
+
+#[test]
+fn nested_chapters_are_kept_raw() {
+	// Deep enough to overflow the stack if each level were parsed
+	let mut chapter = frame(4, b"TIT2", [0, 0], b"\x00Innermost");
+	for i in 0..10_000 {
+		let mut content = format!("ch{}\0", i).into_bytes();
+		content.extend([0; 16]);
+		content.extend(chapter);
+		chapter = frame(4, b"CHAP", [0, 0], &content);
+	}
+	let frames = tag::read_id3_frames(&tag(4, 0, &chapter)).unwrap().0;
+	match &frames[0].data {
+		tag::id3::ID3FrameType::Chapter(f) => {
+			assert_eq!(f.element_id, "ch9999");
+			assert!(matches!(f.frames[0].data, tag::id3::ID3FrameType::Raw(_)));
+		}
+		_ => panic!("CHAP not parsed"),
+	}
+}
+
+/// Frame IDs with their own parsers, including those upgraded from ID3v2.3
+const PARSED_IDS: [&[u8; 4]; 24] = [
+	b"APIC", b"CHAP", b"COMM", b"CTOC", b"EQUA", b"GEOB", b"IPLS", b"PCNT", b"POPM", b"PRIV", b"RVA2", b"RVAD", b"SYLT",
+	b"TDAT", b"TIME", b"TIT2", b"TPE1", b"TRDA", b"TXXX", b"TYER", b"UFID", b"USLT", b"WOAR", b"WXXX",
+];
+
+proptest! {
+	#[test]
+	fn arbitrary_bytes_never_panic(content in proptest::collection::vec(any::<u8>(), 0..256)) {
+		let _ = tag::read_id3_tag(&content);
+		let _ = tag::read_id3_tag_from(&mut Cursor::new(&content));
+		let _ = ID3v1Tag::read(&content);
+		let _ = APETag::read(&content, content.len());
+	}
+
+	#[test]
+	fn arbitrary_tag_bodies_never_panic(
+		version in 2u8..=4,
+		flags in any::<u8>(),
+		body in proptest::collection::vec(any::<u8>(), 0..512),
+	) {
+		let _ = tag::read_id3_tag(&tag(version, flags, &body));
+	}
+
+	#[test]
+	fn arbitrary_frame_content_never_panics(
+		version in 3u8..=4,
+		id in proptest::sample::select(PARSED_IDS.to_vec()),
+		flags in any::<[u8; 2]>(),
+		content in proptest::collection::vec(any::<u8>(), 0..128),
+	) {
+		let _ = tag::read_id3_tag(&tag(version, 0, &frame(version, id, flags, &content)));
+	}
+}
+
+#[test]
+fn tags_cut_inside_a_frame_are_errors() {
+	for version in 3..=4 {
+		let mut body = frame(version, b"TIT2", [0, 0], b"\x00Title");
+		body.extend(frame(version, b"COMM", [0, 0], b"\x03engdesc\x00text"));
+		assert_eq!(body.len(), 39);
+		// Cutting between the frames leaves a valid tag. The header still gives the full size.
+		for cut in (1..39).filter(|cut| *cut != 16) {
+			let result = tag::read_id3_tag(&tag(version, 0, &body)[..10 + cut]);
+			assert!(result.is_err(), "version {} cut at {}", version, cut);
+		}
+	}
+}
@@ -0,0 +1,25 @@
+use tag::mp4::ItemList;
+
+fn item(tag_id: &[u8; 4], data_type: u8, value: &[u8]) -> Vec<u8> {
+	let mut ret = ((24 + value.len()) as u32).to_be_bytes().to_vec();
+	ret.extend(tag_id);
+	ret.extend(((16 + value.len()) as u32).to_be_bytes());
+	ret.extend(b"data");
+	ret.extend([0, 0, 0, data_type, 0, 0, 0, 0]);
+	ret.extend(value);
+	ret
+}
+
+#[test]
+fn rating_without_a_value_is_skipped() {
+	let mut data = Vec::new();
+	data.extend(item(b"\xA9nam", 1, b"Title"));
+	data.extend(item(b"rtng", 0x15, &[]));
+	let mut ilst = ((8 + data.len()) as u32).to_be_bytes().to_vec();
+	ilst.extend(b"ilst");
+	ilst.extend(data);
+
+	let items = ItemList::parse(ilst.len() as u32, &ilst).string(0);
+	assert!(!items.contains("rtng"));
+	assert!(items.contains("Title"));
+}
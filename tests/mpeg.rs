@@ -0,0 +1,158 @@
+use std::io::Cursor;
+
+use tag::mpeg::{AudioProperties, ChannelMode, FrameHeader, MpegVersion, VbrHeader, VbrKind};
+
+/// MPEG-1 Layer III, 128 kbit/s, 44100 Hz, joint stereo, without a CRC
+const HEADER: [u8; 4] = [0xFF, 0xFB, 0x90, 0x40];
+const FRAME_LEN: usize = 417;
+
+/// A frame holding `content` after the header and the side information
+fn frame(content: &[u8]) -> Vec<u8> {
+	let mut ret = HEADER.to_vec();
+	ret.extend([0; 32]);
+	ret.extend(content);
+	ret.resize(FRAME_LEN, 0);
+	ret
+}
+
+/// `junk`, then a stream of `frames` frames, the first holding `first`
+fn stream(junk: &[u8], first: &[u8], frames: usize) -> Vec<u8> {
+	let mut ret = junk.to_vec();
+	ret.extend(frame(first));
+	for _ in 1..frames {
+		ret.extend(frame(b""));
+	}
+	ret
+}
+
+fn properties(content: &[u8], start: u64) -> AudioProperties {
+	AudioProperties::read_from(&mut Cursor::new(content), start, content.len() as u64)
+		.unwrap()
+		.unwrap()
+}
+
+#[test]
+fn frame_headers_are_decoded() {
+	let header = FrameHeader::parse(HEADER).unwrap();
+	assert_eq!(
+		header,
+		FrameHeader {
+			version: MpegVersion::V1,
+			layer: 3,
+			protected: false,
+			bitrate: 128,
+			sample_rate: 44100,
+			padding: false,
+			channel_mode: ChannelMode::JointStereo,
+		}
+	);
+	assert_eq!((header.samples_per_frame(), header.frame_len()), (1152, FRAME_LEN));
+
+	// MPEG-2 Layer III, 32 kbit/s, 16000 Hz, mono
+	let header = FrameHeader::parse([0xFF, 0xF3, 0x48, 0xC4]).unwrap();
+	assert_eq!((header.version, header.layer), (MpegVersion::V2, 3));
+	assert_eq!((header.bitrate, header.sample_rate), (32, 16000));
+	assert_eq!(header.channel_mode, ChannelMode::Mono);
+	assert_eq!((header.samples_per_frame(), header.frame_len()), (576, 144));
+
+	// MPEG-1 Layer I with a CRC and padding, which is 4 bytes
+	let header = FrameHeader::parse([0xFF, 0xFE, 0x92, 0x00]).unwrap();
+	assert_eq!((header.layer, header.protected, header.padding), (1, true, true));
+	assert_eq!(
+		(header.bitrate, header.samples_per_frame(), header.frame_len()),
+		(288, 384, 316)
+	);
+
+	// MPEG-2.5 at 8000 Hz
+	let header = FrameHeader::parse([0xFF, 0xE3, 0x18, 0x00]).unwrap();
+	assert_eq!(
+		(header.version, header.bitrate, header.sample_rate),
+		(MpegVersion::V25, 8, 8000)
+	);
+
+	// No sync, a reserved version, free format and an invalid bitrate
+	for b in [
+		[0xFF, 0x1B, 0x90, 0x40],
+		[0xFF, 0xEB, 0x90, 0x40],
+		[0xFF, 0xFB, 0x00, 0x40],
+		[0xFF, 0xFB, 0xF0, 0x40],
+	] {
+		assert_eq!(FrameHeader::parse(b), None);
+	}
+}
+
+#[test]
+fn cbr_duration_comes_from_the_size() {
+	let content = stream(b"\xFF\xFBjunk", b"", 10);
+	let props = properties(&content, 0);
+	assert_eq!(props.offset, 6);
+	assert_eq!(props.len, 10 * FRAME_LEN as u64);
+	assert!(props.vbr.is_none());
+	// 4170 bytes at 128 kbit/s
+	assert_eq!(props.samples(), 11493);
+	assert_eq!(props.duration_ms(), 260);
+	assert_eq!(props.bitrate(), 128);
+
+	// A frame header which isn't followed by another frame is not audio
+	let mut content = frame(b"");
+	content.extend(b"junk");
+	let props = AudioProperties::read_from(&mut Cursor::new(&content), 0, content.len() as u64).unwrap();
+	assert!(props.is_none());
+}
+
+#[test]
+fn xing_header_gives_the_frame_count() {
+	let mut xing = b"Xing\x00\x00\x00\x0F".to_vec();
+	xing.extend(1000u32.to_be_bytes());
+	xing.extend(417_000u32.to_be_bytes());
+	xing.extend((0..100).map(|i| (i * 256 / 100) as u8));
+	xing.extend(50u32.to_be_bytes());
+	// LAME3.100 with 576 samples of delay and 1000 of padding
+	xing.extend(b"LAME3.100");
+	xing.extend([0; 12]);
+	xing.extend([0x24, 0x03, 0xE8]);
+	let content = stream(b"", &xing, 2);
+
+	let props = properties(&content, 0);
+	let vbr = props.vbr.as_ref().unwrap();
+	assert_eq!(vbr.kind, VbrKind::Xing);
+	assert_eq!(
+		(vbr.frames, vbr.bytes, vbr.quality),
+		(Some(1000), Some(417_000), Some(50))
+	);
+	assert_eq!((vbr.toc.len(), vbr.toc[50]), (100, 128));
+	assert_eq!(vbr.encoder.as_deref(), Some("LAME3.100"));
+	assert_eq!((vbr.encoder_delay, vbr.encoder_padding), (576, 1000));
+	assert_eq!(props.samples(), 1000 * 1152 - 1576);
+	assert_eq!(props.duration_ms(), 26086);
+	assert_eq!(props.bitrate(), 127);
+
+	// Info is the same header in a CBR file, here without the optional fields
+	let header = FrameHeader::parse(HEADER).unwrap();
+	let info = VbrHeader::parse(&header, &frame(b"Info\x00\x00\x00\x01\x00\x00\x00\x0A")).unwrap();
+	assert_eq!((info.kind, info.frames, info.bytes), (VbrKind::Info, Some(10), None));
+	assert!(info.toc.is_empty() && info.encoder.is_none());
+}
+
+#[test]
+fn vbri_header_gives_the_frame_count() {
+	let mut vbri = b"VBRI\x00\x01\x04\x40\x00\x4B".to_vec();
+	vbri.extend(200_000u32.to_be_bytes());
+	vbri.extend(500u32.to_be_bytes());
+	// 2 entries of 2 bytes, scaled by 10, each of 250 frames
+	vbri.extend([0, 2, 0, 10, 0, 2, 0, 250]);
+	vbri.extend([0x27, 0x10, 0x27, 0x10]);
+	let content = stream(b"", &vbri, 3);
+
+	let props = properties(&content, 0);
+	let vbr = props.vbr.as_ref().unwrap();
+	assert_eq!(vbr.kind, VbrKind::Vbri);
+	assert_eq!(
+		(vbr.frames, vbr.bytes, vbr.quality),
+		(Some(500), Some(200_000), Some(75))
+	);
+	assert_eq!(vbr.toc, [100_000, 100_000]);
+	assert_eq!(props.samples(), 500 * 1152);
+	assert_eq!(props.duration_ms(), 13061);
+	assert_eq!(props.bitrate(), 122);
+}
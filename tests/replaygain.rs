@@ -0,0 +1,74 @@
+use tag::{
+	id3::{ID3Frame, ID3FrameType},
+	replaygain::{self, ReplayGain},
+};
+
+mod common;
+use common::{frame, tag};
+
+fn read_frames(body: &[u8]) -> Vec<ID3Frame> {
+	tag::read_id3_frames(&tag(4, 0, body)).unwrap().0
+}
+
+/// -6.5 dB on the master volume, with a peak of 0.75 in 16 bits
+const TRACK_RVA2: &[u8] = b"track\x00\x01\xF3\x00\x10\x60\x00";
+
+#[test]
+fn rva2_is_read() {
+	let frames = read_frames(&frame(4, b"RVA2", [0, 0], TRACK_RVA2));
+	match &frames[0].data {
+		ID3FrameType::RelativeVolume(f) => {
+			assert_eq!(f.identification, "track");
+			assert_eq!(f.channels[0].adjustment, -6 * 512 - 256);
+			assert_eq!(f.channels[0].gain_db(), -6.5);
+			assert_eq!(f.channels[0].peak_ratio(), Some(0.75));
+		}
+		_ => panic!("RVA2 not parsed"),
+	}
+	assert_eq!(frames[0].display(), "RVA2:track:1=-6.50 dB");
+	assert_eq!(frames[0].data.bytes(4, Default::default()), TRACK_RVA2);
+
+	let replay_gain = ReplayGain::from_id3(&frames);
+	assert_eq!(replay_gain.track_gain, Some(-6.5));
+	assert_eq!(replay_gain.track_peak, Some(0.75));
+	assert_eq!(replay_gain.album_gain, None);
+}
+
+#[test]
+fn txxx_is_preferred_to_rva2() {
+	let mut body = frame(4, b"RVA2", [0, 0], TRACK_RVA2);
+	body.extend(frame(4, b"TXXX", [0, 0], b"\x00replaygain_track_gain\x00-7.25 dB"));
+	body.extend(frame(4, b"TXXX", [0, 0], b"\x00REPLAYGAIN_ALBUM_GAIN\x00+1.00 dB"));
+	let replay_gain = ReplayGain::from_id3(&read_frames(&body));
+	assert_eq!(
+		replay_gain,
+		ReplayGain {
+			track_gain: Some(-7.25),
+			track_peak: Some(0.75),
+			album_gain: Some(1.0),
+			album_peak: None,
+		}
+	);
+}
+
+#[test]
+fn every_location_is_written() {
+	let replay_gain = ReplayGain {
+		track_gain: Some(-6.5),
+		track_peak: Some(0.75),
+		..Default::default()
+	};
+	let frames = replay_gain.id3_frames();
+	let displayed: Vec<String> = frames.iter().map(ID3Frame::display).collect();
+	assert_eq!(
+		displayed,
+		[
+			"TXXX:REPLAYGAIN_TRACK_GAIN=-6.50 dB",
+			"TXXX:REPLAYGAIN_TRACK_PEAK=0.750000",
+			"RVA2:track:1=-6.50 dB",
+		]
+	);
+	assert!(frames.iter().all(replaygain::is_replay_gain_frame));
+	assert_eq!(frames[2].data.bytes(4, Default::default()), TRACK_RVA2);
+	assert_eq!(ReplayGain::from_id3(&frames), replay_gain);
+}
@@ -0,0 +1,291 @@
+use tag::id3::{self, ID3Frame, ID3FrameFlags, ID3FrameType, ID3TextFrame};
+
+mod common;
+use common::{frame, tag};
+
+fn text(id: &[u8; 4], value: &str) -> ID3Frame {
+	ID3Frame {
+		id: *id,
+		flags: ID3FrameFlags::default(),
+		data: ID3FrameType::Text(ID3TextFrame {
+			values: vec![value.to_string()],
+			encoding: 0,
+		}),
+	}
+}
+
+fn raw(id: &[u8; 4], data: &[u8]) -> ID3Frame {
+	ID3Frame {
+		id: *id,
+		flags: ID3FrameFlags::default(),
+		data: ID3FrameType::Raw(data.to_vec()),
+	}
+}
+
+fn ids(frames: &[ID3Frame]) -> Vec<String> {
+	frames
+		.iter()
+		.map(|f| String::from_utf8_lossy(&f.id).into_owned())
+		.collect()
+}
+
+fn text_value(frames: &[ID3Frame], id: &[u8; 4]) -> Option<String> {
+	frames.iter().find_map(|f| match &f.data {
+		ID3FrameType::Text(t) if &f.id == id => Some(t.values.join("/")),
+		_ => None,
+	})
+}
+
+#[test]
+fn date_frames_combine_in_any_order() {
+	let frames = vec![
+		text(b"TIT2", "Title"),
+		text(b"TIME", "1405"),
+		text(b"TDAT", "2103"),
+		text(b"TYER", "2004"),
+	];
+	let (frames, unconverted) = id3::upgrade_to_v24(frames);
+	assert!(unconverted.is_empty());
+	assert_eq!(ids(&frames), ["TIT2", "TDRC"]);
+	assert_eq!(text_value(&frames, b"TDRC").as_deref(), Some("2004-03-21T14:05"));
+}
+
+#[test]
+fn date_parts_need_the_parts_before_them() {
+	let (frames, unconverted) = id3::upgrade_to_v24(vec![text(b"TDAT", "2103"), text(b"TIME", "1405")]);
+	assert!(frames.is_empty());
+	assert_eq!(ids(&unconverted), ["TDAT", "TIME"]);
+
+	let (frames, unconverted) = id3::upgrade_to_v24(vec![text(b"TYER", "1999"), text(b"TDAT", "3113")]);
+	assert_eq!(text_value(&frames, b"TDRC").as_deref(), Some("1999"));
+	assert_eq!(ids(&unconverted), ["TDAT"]);
+}
+
+#[test]
+fn recording_dates_give_the_year_without_tyer() {
+	let (frames, unconverted) = id3::upgrade_to_v24(vec![text(b"TRDA", "1987, June 4th-7th")]);
+	assert!(unconverted.is_empty());
+	assert_eq!(text_value(&frames, b"TDRC").as_deref(), Some("1987"));
+
+	let (frames, unconverted) = id3::upgrade_to_v24(vec![text(b"TYER", "1988"), text(b"TRDA", "1987")]);
+	assert_eq!(text_value(&frames, b"TDRC").as_deref(), Some("1988"));
+	assert_eq!(ids(&unconverted), ["TRDA"]);
+}
+
+#[test]
+fn existing_tdrc_is_kept() {
+	let (frames, unconverted) = id3::upgrade_to_v24(vec![text(b"TDRC", "2001-02-03"), text(b"TYER", "1999")]);
+	assert_eq!(ids(&frames), ["TDRC"]);
+	assert_eq!(text_value(&frames, b"TDRC").as_deref(), Some("2001-02-03"));
+	assert_eq!(ids(&unconverted), ["TYER"]);
+}
+
+#[test]
+fn renamed_text_frames() {
+	let frames = vec![
+		text(b"TORY", "1970"),
+		text(b"IPLS", "producer"),
+		text(b"XSOA", "Album, The"),
+		text(b"XSOP", "Artist, The"),
+		text(b"XSOT", "Title, The"),
+	];
+	let (frames, unconverted) = id3::upgrade_to_v24(frames);
+	assert!(unconverted.is_empty());
+	assert_eq!(ids(&frames), ["TDOR", "TIPL", "TSOA", "TSOP", "TSOT"]);
+	assert_eq!(text_value(&frames, b"TSOP").as_deref(), Some("Artist, The"));
+}
+
+#[test]
+fn rvad_becomes_rva2() {
+	// Right raised by half again, left lowered by a quarter, 16 bits each, then the peaks
+	let rvad = [0b01, 16, 0x80, 0x00, 0x40, 0x00, 0x7F, 0xFF, 0x40, 0x00];
+	let (frames, unconverted) = id3::upgrade_to_v24(vec![raw(b"RVAD", &rvad)]);
+	assert!(unconverted.is_empty());
+	assert_eq!(ids(&frames), ["RVA2"]);
+	let rva2 = match &frames[0].data {
+		ID3FrameType::RelativeVolume(f) => f,
+		_ => panic!("RVA2 not parsed"),
+	};
+	assert_eq!(rva2.channels.len(), 2);
+	assert_eq!(rva2.channels[0].channel_type, 2);
+	assert!((rva2.channels[0].gain_db() - 3.52).abs() < 0.01);
+	assert_eq!(rva2.channels[0].peak, [0x7F, 0xFF]);
+	assert_eq!(rva2.channels[1].channel_type, 3);
+	assert!((rva2.channels[1].gain_db() + 2.50).abs() < 0.01);
+	assert_eq!(rva2.channels[1].peak, [0x40, 0x00]);
+}
+
+#[test]
+fn equa_becomes_equ2() {
+	// 1000 Hz raised by half again
+	let equa = [16, 0x83, 0xE8, 0x80, 0x00];
+	let (frames, unconverted) = id3::upgrade_to_v24(vec![raw(b"EQUA", &equa)]);
+	assert!(unconverted.is_empty());
+	assert_eq!(ids(&frames), ["EQU2"]);
+	let adjustment = id3::ID3ChannelVolume::adjustment_from_db(20.0 * 1.5f32.log10()).to_be_bytes();
+	match &frames[0].data {
+		ID3FrameType::Raw(data) => assert_eq!(data, &[1, 0, 0x07, 0xD0, adjustment[0], adjustment[1]]),
+		_ => panic!("EQU2 should be raw"),
+	}
+}
+
+#[test]
+fn malformed_frames_are_reported() {
+	let frames = vec![raw(b"RVAD", &[0, 0]), raw(b"EQUA", &[16, 0x83]), text(b"TIT2", "Title")];
+	let (frames, unconverted) = id3::upgrade_to_v24(frames);
+	assert_eq!(ids(&frames), ["TIT2"]);
+	assert_eq!(ids(&unconverted), ["RVAD", "EQUA"]);
+}
+
+#[test]
+fn read_upgrades_v23_tags() {
+	let mut body = Vec::new();
+	body.extend(frame(3, b"TDAT", [0, 0], b"\x000102"));
+	body.extend(frame(3, b"TIT2", [0, 0], b"\x00Title"));
+	body.extend(frame(3, b"TYER", [0, 0], b"\x002010"));
+	body.extend(frame(3, b"TORY", [0, 0], b"\x001990"));
+	let content = tag(3, 0, &body);
+
+	let (frames, size) = tag::read_id3_frames(&content).unwrap();
+	assert_eq!(size, content.len());
+	assert_eq!(ids(&frames), ["TDRC", "TIT2", "TDOR"]);
+	assert_eq!(text_value(&frames, b"TDRC").as_deref(), Some("2010-02-01"));
+}
+
+#[test]
+fn v23_frames_which_cannot_be_upgraded_are_not_written() {
+	let mut body = Vec::new();
+	body.extend(frame(3, b"TIT2", [0, 0], b"\x00Title"));
+	body.extend(frame(3, b"TDAT", [0, 0], b"\x000102"));
+	body.extend(frame(3, b"TIME", [0, 0], b"\x001405"));
+	let content = tag(3, 0, &body);
+
+	let mut tag = tag::read_id3_tag(&content).unwrap();
+	assert_eq!(ids(&tag.frames), ["TIT2"]);
+	assert_eq!(ids(&tag.unconverted), ["TDAT", "TIME"]);
+
+	tag.header.version_major = 4;
+	let written = tag::read_id3_tag(&tag.bytes()).unwrap();
+	assert_eq!(ids(&written.frames), ["TIT2"]);
+	assert!(written.unconverted.is_empty());
+}
+
+#[test]
+fn v23_slash_separated_frames_are_split() {
+	let mut body = Vec::new();
+	body.extend(frame(3, b"TCOM", [0, 0], b"\x00Lennon/McCartney"));
+	body.extend(frame(3, b"TOPE", [0, 0], b"\x00Original/Artist"));
+	body.extend(frame(3, b"TPUB", [0, 0], b"\x00AC/DC Records"));
+	let content = tag(3, 0, &body);
+
+	let (frames, _) = tag::read_id3_frames(&content).unwrap();
+	let values = |id: &[u8; 4]| match &frames.iter().find(|f| &f.id == id).unwrap().data {
+		ID3FrameType::Text(f) => f.values.clone(),
+		_ => panic!("{} not parsed", String::from_utf8_lossy(id)),
+	};
+	assert_eq!(values(b"TCOM"), ["Lennon", "McCartney"]);
+	assert_eq!(values(b"TOPE"), ["Original", "Artist"]);
+	assert_eq!(values(b"TPUB"), ["AC/DC Records"]);
+}
+
+fn v22_frame(id: &[u8; 3], content: &[u8]) -> Vec<u8> {
+	let mut ret = id.to_vec();
+	ret.extend(&(content.len() as u32).to_be_bytes()[1..]);
+	ret.extend(content);
+	ret
+}
+
+#[test]
+fn v22_frames_without_an_equivalent_are_converted_or_reported() {
+	let mut body = Vec::new();
+	body.extend(v22_frame(b"TT2", b"\x00Title"));
+	body.extend(v22_frame(b"LNK", b"TT2http://example.com/\x00"));
+	body.extend(v22_frame(b"TXY", b"\x00Unknown"));
+	let content = tag(2, 0, &body);
+
+	let mut tag = tag::read_id3_tag(&content).unwrap();
+	// Text frames are kept as user defined text described by their old ID
+	assert_eq!(ids(&tag.frames), ["TIT2", "TXXX"]);
+	match &tag.frames[1].data {
+		ID3FrameType::UserText(f) => {
+			assert_eq!(f.description, "TXY");
+			assert_eq!(f.values, ["Unknown"]);
+		}
+		_ => panic!("TXY should be user defined text"),
+	}
+	// Others are reported, not written
+	assert_eq!(ids(&tag.unconverted), ["LNK "]);
+	match &tag.unconverted[0].data {
+		ID3FrameType::Raw(data) => assert_eq!(data, b"TT2http://example.com/\x00"),
+		_ => panic!("LNK should be raw"),
+	}
+
+	tag.header.version_major = 4;
+	let written = tag::read_id3_tag(&tag.bytes()).unwrap();
+	assert_eq!(ids(&written.frames), ["TIT2", "TXXX"]);
+	assert!(written.unconverted.is_empty());
+}
+
+#[test]
+fn v24_text_is_reencoded_for_v23() {
+	let mut body = Vec::new();
+	body.extend(frame(4, b"TCOM", [0, 0], "\x03Björk\x00Guðmundur".as_bytes()));
+	body.extend(frame(
+		4,
+		b"GEOB",
+		[0, 0],
+		"\x03text/plain\x00ö.txt\x00Ünicode\x00data".as_bytes(),
+	));
+	let content = tag(4, 0, &body);
+
+	let mut tag = tag::read_id3_tag(&content).unwrap();
+	tag.header.version_major = 3;
+	let written = tag.bytes();
+	let mut expected = frame(3, b"TCOM", [0, 0], b"\x00Bj\xF6rk/Gu\xF0mundur");
+	expected.extend(frame(
+		3,
+		b"GEOB",
+		[0, 0],
+		b"\x00text/plain\x00\xF6.txt\x00\xDCnicode\x00data",
+	));
+	assert_eq!(written[10..], expected);
+}
+
+#[test]
+fn v23_tags_are_written_back_as_v23() {
+	let mut body = Vec::new();
+	body.extend(frame(3, b"TIT2", [0, 0], b"\x00Title"));
+	body.extend(frame(
+		3,
+		b"RVAD",
+		[0, 0],
+		&[0b01, 16, 0x80, 0x00, 0x40, 0x00, 0x7F, 0xFF, 0x40, 0x00],
+	));
+	body.extend(frame(3, b"EQUA", [0, 0], &[16, 0x83, 0xE8, 0x80, 0x00]));
+	let content = tag(3, 0, &body);
+
+	let mut tag = tag::read_id3_tag(&content).unwrap();
+	assert_eq!(ids(&tag.frames), ["TIT2", "RVA2", "EQU2"]);
+	tag.header.version_major = 3;
+	let written = tag.bytes();
+	assert_eq!(&written[..4], b"ID3\x03");
+	assert!(written.windows(4).any(|w| w == b"RVAD"));
+	assert!(written.windows(4).any(|w| w == b"EQUA"));
+
+	// The adjustments survive being converted back and forth
+	let reread = tag::read_id3_tag(&written).unwrap();
+	assert_eq!(ids(&reread.frames), ["TIT2", "RVA2", "EQU2"]);
+	let gains = |frames: &[ID3Frame]| match &frames[1].data {
+		ID3FrameType::RelativeVolume(f) => f
+			.channels
+			.iter()
+			.map(|c| (c.channel_type, c.adjustment, c.peak.clone()))
+			.collect::<Vec<_>>(),
+		_ => panic!("RVA2 not parsed"),
+	};
+	assert_eq!(gains(&reread.frames), gains(&tag.frames));
+	match (&reread.frames[2].data, &tag.frames[2].data) {
+		(ID3FrameType::Raw(a), ID3FrameType::Raw(b)) => assert_eq!(a, b),
+		_ => panic!("EQU2 should be raw"),
+	}
+}